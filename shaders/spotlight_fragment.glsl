@@ -0,0 +1,27 @@
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+varying vec2 v_pos;
+uniform vec2 u_center;
+uniform float u_radius;
+uniform float u_feather;
+uniform float u_dim;
+uniform int u_shape;
+// Ширина выхода к высоте
+uniform float u_aspect;
+
+void main() {
+    vec2 d = abs(v_pos - u_center);
+    float dist;
+    if (u_shape == 0) {
+        dist = length(d) - u_radius;
+    } else {
+        // Прямоугольник с пропорциями выхода и тем же "радиусом" по вертикали
+        vec2 half_size = vec2(u_radius * u_aspect, u_radius);
+        dist = length(max(d - half_size, 0.0));
+    }
+    float mask = smoothstep(0.0, max(u_feather, 0.001), dist);
+    gl_FragColor = vec4(0.0, 0.0, 0.0, u_dim * mask);
+}
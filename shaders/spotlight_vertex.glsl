@@ -0,0 +1,8 @@
+attribute vec2 pos;
+uniform mat4 proj;
+varying vec2 v_pos;
+
+void main() {
+    gl_Position = proj * vec4(pos, 0.0, 1.0);
+    v_pos = pos;
+}
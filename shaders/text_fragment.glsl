@@ -0,0 +1,14 @@
+precision mediump float;
+varying float alpha;
+varying vec2 v_uv;
+uniform sampler2D u_tex;
+uniform vec4 u_color;
+uniform float u_image;
+
+void main() {
+    // Атлас глифов хранит только альфу, картинки штампов - полный RGBA
+    vec4 texel = texture2D(u_tex, v_uv);
+    float a = texel.a * u_color.a * alpha;
+    vec3 rgb = mix(u_color.rgb, texel.rgb, u_image);
+    gl_FragColor = vec4(rgb * a, a);
+}
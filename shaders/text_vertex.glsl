@@ -0,0 +1,22 @@
+attribute vec2 pos;
+attribute vec2 uv;
+attribute float t0;
+uniform mat4 proj;
+uniform float u_now;
+uniform float u_ttl;
+uniform float u_fade_start;
+uniform float u_pinned;
+varying float alpha;
+varying vec2 v_uv;
+
+void main() {
+    gl_Position = proj * vec4(pos, 0.0, 1.0);
+    v_uv = uv;
+    float elapsed = u_now - t0;
+    float fade_time = u_ttl - u_fade_start;
+    if (u_pinned < 0.5 && elapsed > u_fade_start) {
+        alpha = clamp(1.0 - (elapsed - u_fade_start) / fade_time, 0.0, 1.0);
+    } else {
+        alpha = 1.0;
+    }
+}
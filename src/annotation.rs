@@ -0,0 +1,65 @@
+use crate::color::Color;
+use crate::text::TextLabel;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub t: f32,
+    // Ширина штриха в этой точке (px)
+    pub w: f32,
+}
+
+pub struct Stroke {
+    // Номер для событий подписчикам
+    pub id: u64,
+    pub points: Vec<Point>,
+    pub color: Color,
+}
+
+pub enum Annotation {
+    Stroke(Stroke),
+    Text(TextLabel),
+    Stamp(Stamp),
+}
+
+impl Annotation {
+    pub fn is_expired(&self, now: f32, ttl: f32) -> bool {
+        match self {
+            Annotation::Stroke(stroke) => match stroke.points.last() {
+                Some(last) => now - last.t >= ttl,
+                None => true,
+            },
+            Annotation::Text(label) => !label.pinned && now - label.t >= ttl,
+            Annotation::Stamp(stamp) => now - stamp.t >= ttl,
+        }
+    }
+}
+
+pub struct Stamp {
+    pub x: f32,
+    pub y: f32,
+    pub kind: StampKind,
+    pub color: Color,
+    pub t: f32,
+}
+
+pub enum StampKind {
+    Number(u32),
+    Symbol(String),
+    Image(String),
+}
+
+impl StampKind {
+    // "number" - нумерованный кружок, "*.png" - картинка из ~/.config/cherta/, иначе символ
+    pub fn from_entry(entry: &str, counter: u32) -> Self {
+        if entry == "number" {
+            StampKind::Number(counter)
+        } else if entry.ends_with(".png") {
+            StampKind::Image(entry.to_string())
+        } else {
+            StampKind::Symbol(entry.to_string())
+        }
+    }
+}
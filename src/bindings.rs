@@ -0,0 +1,424 @@
+use crate::keyboard::keysym_from_name;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+// Привязки кнопок мыши, колеса, кнопок пера и планшета к действиям.
+// Ключ в конфиге: "[модификаторы+]источник:кнопка", например
+// "button:left", "ctrl+scroll:up", "stylus:1", "pad:2".
+// Сочетания клавиш - отдельной таблицей: "ctrl+z", "Escape", "1"
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Action {
+    Draw,
+    Erase,
+    Undo,
+    Clear,
+    CycleTool,
+    CycleColor,
+    ThicknessUp,
+    ThicknessDown,
+    // Штамп из набора; вне штампов сначала выбирает сам инструмент
+    NextStamp,
+    PrevStamp,
+    // Отпустить ввод; повторное срабатывание ничего не меняет
+    Passthrough,
+    TogglePassthrough,
+    ToggleSpotlight,
+    // Цвет из палитры по номеру, с нуля
+    Color(usize),
+    SelectRegion,
+    ClearRegion,
+    ToggleVisibility,
+    // Выравнивание штриха, пока кнопка зажата, как с snap_modifier
+    Snap,
+    Quit,
+    None,
+}
+
+const ACTION_NAMES: [(&str, Action); 19] = [
+    ("draw", Action::Draw),
+    ("erase", Action::Erase),
+    ("undo", Action::Undo),
+    ("clear", Action::Clear),
+    ("cycle-tool", Action::CycleTool),
+    ("cycle-color", Action::CycleColor),
+    ("thickness-up", Action::ThicknessUp),
+    ("thickness-down", Action::ThicknessDown),
+    ("next-stamp", Action::NextStamp),
+    ("prev-stamp", Action::PrevStamp),
+    ("passthrough", Action::Passthrough),
+    ("toggle-passthrough", Action::TogglePassthrough),
+    ("toggle-spotlight", Action::ToggleSpotlight),
+    ("select-region", Action::SelectRegion),
+    ("clear-region", Action::ClearRegion),
+    ("toggle-visibility", Action::ToggleVisibility),
+    ("snap", Action::Snap),
+    ("quit", Action::Quit),
+    ("none", Action::None),
+];
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(n) = s.strip_prefix("color:") {
+            return match n.parse::<usize>() {
+                Ok(n) if n >= 1 => Ok(Action::Color(n - 1)),
+                _ => Err(format!("bad color number: {}", s)),
+            };
+        }
+        ACTION_NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| format!("unknown action: {}", s))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Action::Color(n) = self {
+            return write!(f, "color:{}", n + 1);
+        }
+        let name = ACTION_NAMES
+            .iter()
+            .find(|(_, action)| action == self)
+            .map_or("none", |(name, _)| *name);
+        f.write_str(name)
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        action.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scroll {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    // evdev-код кнопки мыши
+    Button(u32),
+    Scroll(Scroll),
+    // evdev-код кнопки на корпусе пера
+    Stylus(u32),
+    // Номер кнопки планшета, с нуля
+    Pad(u32),
+    // xkb keysym
+    Key(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Mods {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
+const BTN_STYLUS: u32 = 0x14b;
+const BTN_STYLUS2: u32 = 0x14c;
+const BTN_STYLUS3: u32 = 0x149;
+
+pub struct Bindings {
+    map: HashMap<(Mods, Trigger), Action>,
+}
+
+impl Bindings {
+    pub fn new(bindings: &BTreeMap<String, Action>, shortcuts: &BTreeMap<String, Action>) -> Self {
+        let mut map = HashMap::new();
+        for (key, action) in bindings {
+            match parse(key) {
+                Some(binding) => {
+                    map.insert(binding, *action);
+                }
+                None => eprintln!("[bindings] unknown binding: {}", key),
+            }
+        }
+        for (key, action) in shortcuts {
+            match parse_shortcut(key) {
+                Some(binding) => {
+                    map.insert(binding, *action);
+                }
+                None => eprintln!("[bindings] unknown shortcut: {}", key),
+            }
+        }
+        Self { map }
+    }
+
+    // Без точного совпадения модификаторов берётся привязка без них:
+    // Shift+ЛКМ должна рисовать (с выравниванием), а не молчать.
+    // Для клавиш так нельзя - Ctrl+1 не должен выбирать цвет
+    pub fn get(&self, mods: Mods, trigger: Trigger) -> Option<Action> {
+        let fallback = || match trigger {
+            Trigger::Key(_) => None,
+            _ => self.map.get(&(Mods::default(), trigger)),
+        };
+        self.map
+            .get(&(mods, trigger))
+            .or_else(fallback)
+            .copied()
+            .filter(|action| *action != Action::None)
+    }
+}
+
+fn split_mods(key: &str) -> Option<(Mods, &str)> {
+    let mut mods = Mods::default();
+    let mut parts: Vec<&str> = key.split('+').map(str::trim).collect();
+    let last = parts.pop()?;
+    for part in parts {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "control" => mods.ctrl = true,
+            "shift" => mods.shift = true,
+            "alt" => mods.alt = true,
+            "super" | "logo" => mods.logo = true,
+            _ => return None,
+        }
+    }
+    Some((mods, last))
+}
+
+pub fn parse(key: &str) -> Option<(Mods, Trigger)> {
+    let (mods, trigger) = split_mods(key)?;
+    let (kind, name) = trigger.split_once(':')?;
+    let name = name.to_lowercase();
+    let trigger = match kind.to_lowercase().as_str() {
+        "button" => Trigger::Button(match name.as_str() {
+            "left" => BTN_LEFT,
+            "right" => BTN_RIGHT,
+            "middle" => BTN_MIDDLE,
+            "side" | "back" => BTN_SIDE,
+            "extra" | "forward" => BTN_EXTRA,
+            code => parse_code(code)?,
+        }),
+        "scroll" => Trigger::Scroll(match name.as_str() {
+            "up" => Scroll::Up,
+            "down" => Scroll::Down,
+            "left" => Scroll::Left,
+            "right" => Scroll::Right,
+            _ => return None,
+        }),
+        "stylus" => Trigger::Stylus(match name.as_str() {
+            "1" => BTN_STYLUS,
+            "2" => BTN_STYLUS2,
+            "3" => BTN_STYLUS3,
+            code => parse_code(code)?,
+        }),
+        // Для человека кнопки нумеруются с единицы
+        "pad" => Trigger::Pad(name.parse::<u32>().ok()?.checked_sub(1)?),
+        _ => return None,
+    };
+    Some((mods, trigger))
+}
+
+pub fn parse_shortcut(key: &str) -> Option<(Mods, Trigger)> {
+    let (mods, name) = split_mods(key)?;
+    let sym = keysym_from_name(name)?;
+    Some((mods, Trigger::Key(sym.raw())))
+}
+
+fn parse_code(code: &str) -> Option<u32> {
+    match code.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => code.parse().ok(),
+    }
+}
+
+pub fn default_bindings() -> BTreeMap<String, Action> {
+    [
+        ("button:left", Action::Draw),
+        ("button:right", Action::CycleTool),
+        ("ctrl+button:right", Action::CycleColor),
+        ("button:middle", Action::ToggleSpotlight),
+        ("button:side", Action::Undo),
+        ("button:extra", Action::Clear),
+        ("scroll:up", Action::Passthrough),
+        ("scroll:down", Action::Passthrough),
+        ("scroll:left", Action::Passthrough),
+        ("scroll:right", Action::Passthrough),
+        ("ctrl+scroll:up", Action::ThicknessUp),
+        ("ctrl+scroll:down", Action::ThicknessDown),
+        ("shift+scroll:up", Action::PrevStamp),
+        ("shift+scroll:down", Action::NextStamp),
+        ("pad:1", Action::CycleTool),
+        ("pad:2", Action::Undo),
+        ("pad:3", Action::Clear),
+        ("pad:4", Action::ToggleSpotlight),
+    ]
+    .into_iter()
+    .map(|(key, action)| (key.to_string(), action))
+    .collect()
+}
+
+pub fn default_shortcuts() -> BTreeMap<String, Action> {
+    let mut shortcuts: BTreeMap<String, Action> = [
+        ("Escape", Action::TogglePassthrough),
+        ("ctrl+z", Action::Undo),
+        ("Delete", Action::Clear),
+        ("Tab", Action::CycleTool),
+        ("s", Action::ToggleSpotlight),
+        ("r", Action::SelectRegion),
+        ("shift+r", Action::ClearRegion),
+        ("h", Action::ToggleVisibility),
+        ("ctrl+q", Action::Quit),
+    ]
+    .into_iter()
+    .map(|(key, action)| (key.to_string(), action))
+    .collect();
+    for n in 1..=9 {
+        shortcuts.insert(n.to_string(), Action::Color(n - 1));
+    }
+    shortcuts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL: Mods = Mods {
+        ctrl: true,
+        shift: false,
+        alt: false,
+        logo: false,
+    };
+    const SHIFT: Mods = Mods {
+        ctrl: false,
+        shift: true,
+        alt: false,
+        logo: false,
+    };
+
+    #[test]
+    fn parses_sources_and_modifiers() {
+        assert_eq!(
+            parse("button:left"),
+            Some((Mods::default(), Trigger::Button(BTN_LEFT)))
+        );
+        assert_eq!(
+            parse("Ctrl + Scroll:Up"),
+            Some((CTRL, Trigger::Scroll(Scroll::Up)))
+        );
+        assert_eq!(
+            parse("shift+button:back"),
+            Some((SHIFT, Trigger::Button(BTN_SIDE)))
+        );
+        assert_eq!(
+            parse("button:0x115"),
+            Some((Mods::default(), Trigger::Button(0x115)))
+        );
+        assert_eq!(
+            parse("stylus:2"),
+            Some((Mods::default(), Trigger::Stylus(BTN_STYLUS2)))
+        );
+        assert_eq!(parse("pad:1"), Some((Mods::default(), Trigger::Pad(0))));
+        let (mods, _) = parse("ctrl+alt+super+shift+pad:3").unwrap();
+        assert!(mods.ctrl && mods.alt && mods.logo && mods.shift);
+    }
+
+    #[test]
+    fn rejects_bad_combos() {
+        for key in [
+            "",
+            "button",
+            "button:",
+            "button:huge",
+            "scroll:sideways",
+            "pad:0",
+            "pad:x",
+            "wheel:up",
+            "hyper+button:left",
+            "ctrl+",
+        ] {
+            assert_eq!(parse(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn action_names_round_trip() {
+        for (name, action) in ACTION_NAMES {
+            assert_eq!(name.parse::<Action>(), Ok(action));
+            assert_eq!(action.to_string(), name);
+        }
+        assert_eq!("color:2".parse::<Action>(), Ok(Action::Color(1)));
+        assert_eq!(Action::Color(1).to_string(), "color:2");
+        assert!("color:0".parse::<Action>().is_err());
+        assert!("fly".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn defaults_parse_and_fall_back() {
+        let bindings = Bindings::new(&default_bindings(), &BTreeMap::new());
+        let left = Trigger::Button(BTN_LEFT);
+        let up = Trigger::Scroll(Scroll::Up);
+        assert_eq!(bindings.get(Mods::default(), left), Some(Action::Draw));
+        // Без своей привязки Shift+ЛКМ рисует, как ЛКМ
+        assert_eq!(bindings.get(SHIFT, left), Some(Action::Draw));
+        assert_eq!(bindings.get(CTRL, up), Some(Action::ThicknessUp));
+        assert_eq!(bindings.get(SHIFT, up), Some(Action::PrevStamp));
+        assert_eq!(bindings.get(Mods::default(), up), Some(Action::Passthrough));
+        assert_eq!(
+            bindings.get(Mods::default(), Trigger::Stylus(BTN_STYLUS)),
+            None
+        );
+        assert!(default_bindings().keys().all(|key| parse(key).is_some()));
+    }
+
+    #[test]
+    fn snap_binds_to_buttons() {
+        let bindings = Bindings::new(
+            &BTreeMap::from([
+                ("button:side".to_string(), "snap".parse().unwrap()),
+                ("stylus:2".to_string(), Action::Snap),
+            ]),
+            &BTreeMap::new(),
+        );
+        assert_eq!(
+            bindings.get(Mods::default(), Trigger::Button(BTN_SIDE)),
+            Some(Action::Snap)
+        );
+        assert_eq!(
+            bindings.get(Mods::default(), Trigger::Stylus(BTN_STYLUS2)),
+            Some(Action::Snap)
+        );
+    }
+
+    #[test]
+    fn keys_do_not_fall_back_and_none_unbinds() {
+        let z = Trigger::Key(0x7a);
+        let right = Trigger::Button(BTN_RIGHT);
+        let bindings = Bindings {
+            map: HashMap::from([
+                ((Mods::default(), z), Action::Undo),
+                ((Mods::default(), right), Action::CycleTool),
+                ((CTRL, right), Action::None),
+            ]),
+        };
+        assert_eq!(bindings.get(Mods::default(), z), Some(Action::Undo));
+        assert_eq!(bindings.get(CTRL, z), None);
+        assert_eq!(bindings.get(CTRL, right), None);
+        assert_eq!(bindings.get(SHIFT, right), Some(Action::CycleTool));
+    }
+}
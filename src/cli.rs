@@ -0,0 +1,574 @@
+use crate::color::ColorChoice;
+use crate::config::Config;
+use crate::control;
+use crate::events::OverlayEvent;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+// Командная строка: `cherta [run] [опции]` запускает оверлей, остальные
+// подкоманды работают без него или с уже запущенным экземпляром
+
+const HELP: &str = "\
+cherta - screen annotator for Wayland
+
+Usage:
+  cherta [run] [options]        start the overlay, or forward flags to a running one
+  cherta ctl <command> [value]  send a command to the running instance
+  cherta config show            print the effective configuration
+  cherta config validate        check the configuration file
+  cherta config init [--force]  write the default configuration
+  cherta export [path]          save the current annotations as SVG
+  cherta replay <file>          redraw strokes recorded with `cherta ctl subscribe`
+
+Config options (run, config):
+  --config <path>     read configuration from <path>
+  --profile <name>    read <name>.toml from $XDG_CONFIG_HOME/cherta (or
+                      /etc/xdg/cherta) on top of default.toml; switches a
+                      running instance to that profile
+
+Overrides (run):
+  --color <color>     palette number, #rrggbb[aa], CSS name or hsl(...)
+  --thickness <px>    stroke thickness
+  --ttl <seconds>     time before annotations fade out
+  --output <name>     output to cover, e.g. DP-1
+
+Commands for a running instance (run):
+  --toggle --capture --release --show --hide --clear --undo --quit --export
+  --tool <name> --width <scale>
+
+  -h, --help          print this help
+  -V, --version       print the version
+
+Environment:
+  CHERTA_<KEY>        override a config setting, e.g. CHERTA_TTL=10,
+                      CHERTA_COLOR=tomato or CHERTA_RENDERER_FEATHER=1.5
+";
+
+pub enum Command {
+    Run(RunOptions),
+    Ctl(Vec<String>),
+    ConfigShow(ConfigSource),
+    ConfigValidate(ConfigSource),
+    ConfigInit(ConfigSource, bool),
+    Export(Option<PathBuf>),
+    Replay(PathBuf),
+    Help,
+    Version,
+}
+
+// Откуда читать конфиг: файл по умолчанию, --config или --profile
+#[derive(Default)]
+pub struct ConfigSource {
+    pub config: Option<PathBuf>,
+    pub profile: Option<String>,
+}
+
+impl ConfigSource {
+    pub fn path(&self) -> PathBuf {
+        match (&self.config, &self.profile) {
+            (Some(path), _) => path.clone(),
+            (None, Some(name)) => Config::profile_path(name),
+            (None, None) => Config::path(),
+        }
+    }
+
+    // Куда писать: системные файлы в /etc/xdg не трогаем, копия для
+    // пользователя ложится в его каталог
+    pub fn user_path(&self) -> PathBuf {
+        match (&self.config, &self.profile) {
+            (Some(path), _) => path.clone(),
+            (None, Some(name)) => Config::dir().join(format!("{}.toml", name)),
+            (None, None) => Config::dir().join("default.toml"),
+        }
+    }
+
+    // Конфиг по умолчанию создаётся при первом запуске, явно указанный
+    // файл должен существовать
+    pub fn load(&self) -> Result<Config, String> {
+        match (&self.config, &self.profile) {
+            (Some(path), _) => Config::load_from(path),
+            (None, Some(name)) => Config::load_profile(name),
+            (None, None) => Ok(Config::load()),
+        }
+    }
+
+    // Для config show и config validate: файлы только читаются
+    pub fn inspect(&self) -> Result<Config, String> {
+        match (&self.config, &self.profile) {
+            (Some(path), _) => Config::inspect_from(path),
+            (None, Some(name)) => Config::inspect_profile(name),
+            (None, None) => Config::inspect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RunOptions {
+    pub source: ConfigSource,
+    pub color: Option<String>,
+    pub thickness: Option<f32>,
+    pub ttl: Option<f32>,
+    pub output: Option<String>,
+    // Команды управляющего сокета: "toggle", "tool eraser"
+    pub commands: Vec<String>,
+}
+
+impl RunOptions {
+    // Флаги поверх загруженного конфига. Проверка - уже с ними: --ttl меньше
+    // заданного в файле fade_start так же неверен, как и сам файл
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(color) = &self.color {
+            // Синтаксис проверен при разборе, номер - по палитре конфига
+            config.pen.color = color
+                .parse::<ColorChoice>()
+                .and_then(|choice| choice.resolve(&config.pen.palette))
+                .map_err(|e| format!("--color: {}", e))?;
+        }
+        if let Some(thickness) = self.thickness {
+            // Заданный в конфиге диапазон variable_width растягивается вместе
+            // с толщиной, иначе флаг ничего бы не менял
+            let scale = thickness / config.pen.thickness;
+            config.min_width = config.min_width.map(|w| w * scale);
+            config.max_width = config.max_width.map(|w| w * scale);
+            config.pen.thickness = thickness;
+        }
+        if let Some(ttl) = self.ttl {
+            config.ttl = ttl;
+        }
+        if let Some(output) = &self.output {
+            config.output = Some(output.clone());
+        }
+        config.validate()
+    }
+
+    // Что переслать уже запущенному экземпляру
+    pub fn forwarded(&self) -> Vec<String> {
+        if self.thickness.is_some() || self.output.is_some() || self.source.config.is_some() {
+            eprintln!("cherta: --config, --thickness and --output only apply to a new instance");
+        }
+        let mut commands = Vec::new();
+        // Профиль первым, чтобы остальные флаги легли поверх него
+        if let Some(name) = &self.source.profile {
+            commands.push(format!("profile {}", name));
+        }
+        commands.extend(self.commands.iter().cloned());
+        if let Some(color) = &self.color {
+            commands.push(format!("color {}", color));
+        }
+        if let Some(ttl) = self.ttl {
+            commands.push(format!("ttl {}", ttl));
+        }
+        commands
+    }
+}
+
+const SWITCHES: [&str; 9] = [
+    "toggle", "capture", "release", "show", "hide", "clear", "undo", "quit", "export",
+];
+const FORWARDED_WITH_VALUE: [&str; 2] = ["tool", "width"];
+
+pub fn parse(args: Vec<String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut rest: Vec<String> = Vec::new();
+    let first = args.next();
+    match first.as_deref() {
+        None => return Ok(Command::Run(RunOptions::default())),
+        Some("run") => {}
+        Some("ctl") => return Ok(Command::Ctl(args.collect())),
+        Some("config") => {
+            let action = args.next().ok_or("config needs show, validate or init")?;
+            let mut source = ConfigSource::default();
+            let mut force = false;
+            let mut options = Options::new(args);
+            while let Some((name, value)) = options.next()? {
+                match name.as_str() {
+                    "--force" if action == "init" => force = true,
+                    _ => parse_source(&name, value, &mut options, &mut source)?,
+                }
+            }
+            return match action.as_str() {
+                "show" => Ok(Command::ConfigShow(source)),
+                "validate" => Ok(Command::ConfigValidate(source)),
+                "init" => Ok(Command::ConfigInit(source, force)),
+                _ => Err(format!("unknown config command: {}", action)),
+            };
+        }
+        Some("export") => {
+            let path = args.next().map(PathBuf::from);
+            return match args.next() {
+                Some(extra) => Err(format!("unexpected argument: {}", extra)),
+                None => Ok(Command::Export(path)),
+            };
+        }
+        Some("replay") => {
+            let path = args.next().ok_or("replay needs a file")?;
+            return Ok(Command::Replay(PathBuf::from(path)));
+        }
+        Some("help" | "-h" | "--help") => return Ok(Command::Help),
+        Some("version" | "-V" | "--version") => return Ok(Command::Version),
+        Some(arg) if !arg.starts_with('-') => return Err(format!("unknown command: {}", arg)),
+        Some(_) => rest.extend(first),
+    }
+    rest.extend(args);
+
+    let mut run = RunOptions::default();
+    let mut options = Options::new(rest.into_iter());
+    while let Some((name, value)) = options.next()? {
+        let flag = name.strip_prefix("--").unwrap_or_default();
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--color" => {
+                let color = options.value(&name, value)?;
+                color.parse::<ColorChoice>()?;
+                run.color = Some(color);
+            }
+            "--thickness" => run.thickness = Some(positive(&name, &options.value(&name, value)?)?),
+            "--ttl" => run.ttl = Some(positive(&name, &options.value(&name, value)?)?),
+            "--output" => run.output = Some(options.value(&name, value)?),
+            _ if SWITCHES.contains(&flag) && value.is_none() => run.commands.push(flag.to_string()),
+            _ if FORWARDED_WITH_VALUE.contains(&flag) => {
+                let value = options.value(&name, value)?;
+                run.commands.push(format!("{} {}", flag, value));
+            }
+            _ => parse_source(&name, value, &mut options, &mut run.source)?,
+        }
+    }
+    Ok(Command::Run(run))
+}
+
+fn parse_source(
+    name: &str,
+    value: Option<String>,
+    options: &mut Options<impl Iterator<Item = String>>,
+    source: &mut ConfigSource,
+) -> Result<(), String> {
+    match name {
+        "--config" => source.config = Some(PathBuf::from(options.value(name, value)?)),
+        "--profile" => source.profile = Some(options.value(name, value)?),
+        _ if name.starts_with('-') => return Err(format!("unknown option: {}", name)),
+        _ => return Err(format!("unexpected argument: {}", name)),
+    }
+    if source.config.is_some() && source.profile.is_some() {
+        return Err("--config and --profile cannot be used together".to_string());
+    }
+    Ok(())
+}
+
+fn positive(name: &str, value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("{} needs a positive number, got {}", name, value))
+}
+
+// Опции вида "--name value" и "--name=value"
+struct Options<I> {
+    args: I,
+}
+
+impl<I: Iterator<Item = String>> Options<I> {
+    fn new(args: I) -> Self {
+        Self { args }
+    }
+
+    fn next(&mut self) -> Result<Option<(String, Option<String>)>, String> {
+        let Some(arg) = self.args.next() else {
+            return Ok(None);
+        };
+        match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                Ok(Some((name.to_string(), Some(value.to_string()))))
+            }
+            _ => Ok(Some((arg, None))),
+        }
+    }
+
+    fn value(&mut self, name: &str, value: Option<String>) -> Result<String, String> {
+        value
+            .or_else(|| self.args.next())
+            .ok_or_else(|| format!("{} needs a value", name))
+    }
+}
+
+// Подкоманды без оверлея; возвращает код выхода процесса
+pub fn execute(command: Command) -> i32 {
+    match command {
+        Command::Run(_) => unreachable!("run is handled by main"),
+        Command::Ctl(args) => control::run_client(&args),
+        Command::ConfigShow(source) => match source.inspect() {
+            Ok(config) => match toml::to_string_pretty(&config) {
+                Ok(data) => {
+                    print!("{}", data);
+                    0
+                }
+                Err(e) => fail(e),
+            },
+            Err(e) => fail(e),
+        },
+        Command::ConfigValidate(source) => {
+            // Как и config show: без default.toml проверяются встроенные значения
+            let path = source.path();
+            match source.inspect() {
+                Ok(_) => {
+                    println!("{}: ok", path.display());
+                    0
+                }
+                Err(e) => fail(e),
+            }
+        }
+        Command::ConfigInit(source, force) => {
+            let path = source.user_path();
+            if path.exists() && !force {
+                return fail(format!(
+                    "{} already exists, use --force to overwrite",
+                    path.display()
+                ));
+            }
+            match Config::default().save_to(&path) {
+                Ok(()) => 0,
+                Err(e) => fail(e),
+            }
+        }
+        Command::Export(path) => {
+            // Путь считается от каталога клиента, а не запущенного экземпляра
+            let path = path.map(|path| match std::env::current_dir() {
+                Ok(dir) => dir.join(path),
+                Err(_) => path,
+            });
+            let command = match path {
+                Some(path) => format!("export {}", path.display()),
+                None => "export".to_string(),
+            };
+            control::run_client(&[command])
+        }
+        Command::Replay(path) => replay(&path),
+        Command::Help => {
+            print!("{}", HELP);
+            0
+        }
+        Command::Version => {
+            println!("cherta {}", env!("CARGO_PKG_VERSION"));
+            0
+        }
+    }
+}
+
+pub fn usage_error(message: &str) -> ! {
+    eprintln!(
+        "cherta: {}\nTry 'cherta --help' for more information.",
+        message
+    );
+    std::process::exit(2);
+}
+
+fn fail(message: impl std::fmt::Display) -> i32 {
+    eprintln!("cherta: {}", message);
+    1
+}
+
+// Паузы между штрихами длиннее этой сокращаются
+const MAX_REPLAY_PAUSE: f32 = 2.0;
+
+// Штрихи из записи подписки отправляются запущенному экземпляру в том же темпе
+fn replay(path: &PathBuf) -> i32 {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) => return fail(format!("{}: {}", path.display(), e)),
+    };
+    let mut previous: Option<f32> = None;
+    let mut count = 0;
+    for line in data.lines() {
+        let Ok(OverlayEvent::StrokeFinished { color, points, .. }) = serde_json::from_str(line)
+        else {
+            continue;
+        };
+        let Some(end) = points.last().map(|p| p.t) else {
+            continue;
+        };
+        if let Some(previous) = previous {
+            let pause = (end - previous).clamp(0.0, MAX_REPLAY_PAUSE);
+            thread::sleep(Duration::from_secs_f32(pause));
+        }
+        previous = Some(end);
+
+        let stroke = serde_json::json!({ "color": color, "points": points });
+        match control::send(&format!("stroke {}", stroke)) {
+            Ok(reply) if reply.starts_with("ok") => count += 1,
+            Ok(reply) if reply.is_empty() => return fail("connection closed by cherta"),
+            Ok(reply) => return fail(reply.trim()),
+            Err(e) => return fail(e),
+        }
+    }
+    println!("replayed {} strokes", count);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn run(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    fn run_options(args: &[&str]) -> RunOptions {
+        match run(args) {
+            Ok(Command::Run(options)) => options,
+            Ok(_) => panic!("{:?}: not a run command", args),
+            Err(e) => panic!("{:?}: {}", args, e),
+        }
+    }
+
+    #[test]
+    fn no_arguments_runs_the_overlay() {
+        let options = run_options(&[]);
+        assert!(options.commands.is_empty());
+        assert!(options.source.config.is_none() && options.source.profile.is_none());
+        assert!(options.color.is_none() && options.ttl.is_none());
+        assert!(options.forwarded().is_empty());
+    }
+
+    #[test]
+    fn run_flags() {
+        let options = run_options(&[
+            "run", "--ttl=5", "--color", "2", "--toggle", "--tool", "eraser",
+        ]);
+        assert_eq!(options.ttl, Some(5.0));
+        assert_eq!(options.color.as_deref(), Some("2"));
+        assert_eq!(options.commands, ["toggle", "tool eraser"]);
+        // Без "run" - то же самое
+        let options = run_options(&["--profile", "review", "--clear"]);
+        assert_eq!(options.source.profile.as_deref(), Some("review"));
+        assert_eq!(options.forwarded(), ["profile review", "clear"]);
+    }
+
+    #[test]
+    fn unknown_commands_and_options() {
+        assert_eq!(
+            run(&["frobnicate"]).err().as_deref(),
+            Some("unknown command: frobnicate")
+        );
+        assert_eq!(
+            run(&["--frobnicate"]).err().as_deref(),
+            Some("unknown option: --frobnicate")
+        );
+        assert_eq!(
+            run(&["config", "frob"]).err().as_deref(),
+            Some("unknown config command: frob")
+        );
+        assert!(run(&["config"]).is_err());
+        assert!(run(&["config", "show", "--force"]).is_err());
+        assert!(run(&["export", "a.svg", "b.svg"]).is_err());
+    }
+
+    #[test]
+    fn missing_and_bad_values() {
+        assert_eq!(
+            run(&["--config"]).err().as_deref(),
+            Some("--config needs a value")
+        );
+        assert_eq!(
+            run(&["config", "show", "--profile"]).err().as_deref(),
+            Some("--profile needs a value")
+        );
+        assert!(run(&["--ttl", "0"]).is_err());
+        assert!(run(&["--thickness", "big"]).is_err());
+        assert!(run(&["--color", "notacolor"]).is_err());
+        assert!(run(&["--toggle=yes"]).is_err());
+        assert_eq!(
+            run(&["--config", "a.toml", "--profile", "b"])
+                .err()
+                .as_deref(),
+            Some("--config and --profile cannot be used together")
+        );
+    }
+
+    #[test]
+    fn thickness_sets_stroke_width() {
+        let mut config = Config::default();
+        run_options(&["--thickness", "6"])
+            .apply(&mut config)
+            .unwrap();
+        // Медленный штрих толще быстрого, оба вдвое толще, чем по умолчанию
+        assert_eq!(config.stroke_width(0.0), 10.0);
+        assert_eq!(config.stroke_width(1.0), 3.0);
+
+        let mut config = Config {
+            min_width: Some(1.0),
+            max_width: Some(4.0),
+            ..Config::default()
+        };
+        run_options(&["--thickness", "6"])
+            .apply(&mut config)
+            .unwrap();
+        assert_eq!(config.stroke_width(0.0), 8.0);
+        assert_eq!(config.stroke_width(1.0), 2.0);
+
+        let mut config = Config {
+            variable_width: false,
+            ..Config::default()
+        };
+        run_options(&["--thickness", "6"])
+            .apply(&mut config)
+            .unwrap();
+        assert_eq!(config.stroke_width(0.0), 6.0);
+        assert_eq!(config.stroke_width(1.0), 6.0);
+    }
+
+    #[test]
+    fn ttl_is_checked_against_fade_start() {
+        let mut config = Config {
+            fade_start: Some(2.0),
+            ..Config::default()
+        };
+        let error = run_options(&["--ttl", "1"]).apply(&mut config).unwrap_err();
+        assert!(error.contains("fade_start"), "{}", error);
+        let mut config = Config::default();
+        run_options(&["--ttl", "1"]).apply(&mut config).unwrap();
+        assert_eq!(config.fade_start(), 0.75);
+    }
+
+    #[test]
+    fn palette_number_out_of_range_is_an_error() {
+        let mut config = Config::default();
+        let count = config.pen.palette.len().to_string();
+        run_options(&["--color", &count])
+            .apply(&mut config)
+            .unwrap();
+        assert_eq!(config.pen.color, *config.pen.palette.last().unwrap());
+        let too_far = (config.pen.palette.len() + 1).to_string();
+        let error = run_options(&["--color", &too_far])
+            .apply(&mut config)
+            .unwrap_err();
+        assert!(error.starts_with("--color"), "{}", error);
+    }
+
+    #[test]
+    fn subcommands() {
+        assert!(matches!(run(&["help"]), Ok(Command::Help)));
+        assert!(matches!(run(&["-V"]), Ok(Command::Version)));
+        assert!(matches!(run(&["run", "--help"]), Ok(Command::Help)));
+        assert!(
+            matches!(run(&["ctl", "tool", "pen"]), Ok(Command::Ctl(args)) if args == ["tool", "pen"])
+        );
+        assert!(matches!(
+            run(&["config", "init", "--force"]),
+            Ok(Command::ConfigInit(_, true))
+        ));
+        assert!(matches!(
+            run(&["config", "validate", "--config=x.toml"]),
+            Ok(Command::ConfigValidate(source)) if source.path() == Path::new("x.toml")
+        ));
+        assert!(matches!(run(&["export"]), Ok(Command::Export(None))));
+        assert!(matches!(
+            run(&["replay", "log.jsonl"]),
+            Ok(Command::Replay(_))
+        ));
+        assert!(run(&["replay"]).is_err());
+    }
+}
@@ -0,0 +1,439 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+// Цвет с прозрачностью. В конфиге и командах - строка: "#ff0000",
+// "#ff000080", CSS-имя ("tomato") или "hsl(0, 100%, 50%)"; для старых
+// конфигов и записей событий - массив [r, g, b] или [r, g, b, a] от 0 до 1
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub rgb: [f32; 3],
+    pub alpha: f32,
+}
+
+impl Color {
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self {
+            rgb: [r, g, b],
+            alpha: 1.0,
+        }
+    }
+
+    pub fn to_array(self) -> [f32; 4] {
+        let [r, g, b] = self.rgb;
+        [r, g, b, self.alpha]
+    }
+
+    fn from_array(values: &[f32]) -> Result<Self, String> {
+        if !values.iter().all(|v| (0.0..=1.0).contains(v)) {
+            return Err("color channels must be in 0..1".to_string());
+        }
+        match *values {
+            [r, g, b] => Ok(Self::rgb(r, g, b)),
+            [r, g, b, alpha] => Ok(Self {
+                rgb: [r, g, b],
+                alpha,
+            }),
+            _ => Err("a color array needs 3 or 4 channels".to_string()),
+        }
+    }
+
+    fn from_hex(hex: &str) -> Option<Self> {
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        // "#f00" и "#f008" - по цифре на канал
+        let digits: Vec<u8> = match hex.len() {
+            3 | 4 => hex.bytes().flat_map(|b| [b, b]).collect(),
+            6 | 8 => hex.bytes().collect(),
+            _ => return None,
+        };
+        let channels: Vec<f32> = digits
+            .chunks(2)
+            .map(|pair| {
+                let pair = std::str::from_utf8(pair).unwrap_or_default();
+                u8::from_str_radix(pair, 16).unwrap_or_default() as f32 / 255.0
+            })
+            .collect();
+        Self::from_array(&channels).ok()
+    }
+
+    // "hsl(200, 80%, 50%)", "hsla(200, 80%, 50%, 0.5)", "hsl(200deg 80% 50% / 50%)"
+    fn from_hsl(s: &str) -> Option<Self> {
+        let inner = s
+            .strip_prefix("hsla(")
+            .or_else(|| s.strip_prefix("hsl("))?
+            .strip_suffix(')')?;
+        let inner = inner.replace([',', '/'], " ");
+        let parts: Vec<&str> = inner.split_whitespace().collect();
+        let (h, s, l, alpha) = match parts[..] {
+            [h, s, l] => (h, s, l, None),
+            [h, s, l, alpha] => (h, s, l, Some(alpha)),
+            _ => return None,
+        };
+        let h = h.strip_suffix("deg").unwrap_or(h).parse::<f32>().ok()?;
+        let s = percent(s)?;
+        let l = percent(l)?;
+        let alpha = match alpha {
+            Some(alpha) if alpha.ends_with('%') => percent(alpha)?,
+            Some(alpha) => alpha.parse::<f32>().ok()?,
+            None => 1.0,
+        };
+
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        // При s = 100% и l < 50% m уходит чуть ниже нуля из-за округления
+        let m = l - c / 2.0;
+        let channel = |v: f32| (v + m).clamp(0.0, 1.0);
+        Self::from_array(&[channel(r), channel(g), channel(b), alpha]).ok()
+    }
+}
+
+// "50%" -> 0.5
+fn percent(s: &str) -> Option<f32> {
+    let value = s.strip_suffix('%')?.parse::<f32>().ok()? / 100.0;
+    (0.0..=1.0).contains(&value).then_some(value)
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let color = if let Some(hex) = lower.strip_prefix('#') {
+            Self::from_hex(hex)
+        } else if lower.starts_with("hsl") {
+            Self::from_hsl(&lower)
+        } else {
+            CSS_NAMES
+                .iter()
+                .find(|(name, _)| *name == lower)
+                .and_then(|(_, hex)| Self::from_hex(hex))
+        };
+        color.ok_or_else(|| format!("bad color: {}", s))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let [r, g, b] = self.rgb.map(byte);
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)?;
+        if self.alpha < 1.0 {
+            write!(f, "{:02x}", byte(self.alpha))?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Channels(Vec<f32>),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Text(s) => s.parse(),
+            Repr::Channels(values) => Color::from_array(&values),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+// Цвет из командной строки или сокета: номер в палитре с единицы или сам цвет
+pub enum ColorChoice {
+    Palette(usize),
+    Color(Color),
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(n) if n >= 1 => Ok(ColorChoice::Palette(n - 1)),
+            Ok(_) => Err(format!("bad color: {}", s)),
+            Err(_) => s.parse().map(ColorChoice::Color),
+        }
+    }
+}
+
+impl ColorChoice {
+    pub fn resolve(&self, palette: &[Color]) -> Result<Color, String> {
+        match self {
+            ColorChoice::Palette(n) => palette
+                .get(*n)
+                .copied()
+                .ok_or_else(|| format!("no color {} in a palette of {}", n + 1, palette.len())),
+            ColorChoice::Color(color) => Ok(*color),
+        }
+    }
+}
+
+// Именованные цвета CSS
+const CSS_NAMES: [(&str, &str); 148] = [
+    ("aliceblue", "f0f8ff"),
+    ("antiquewhite", "faebd7"),
+    ("aqua", "00ffff"),
+    ("aquamarine", "7fffd4"),
+    ("azure", "f0ffff"),
+    ("beige", "f5f5dc"),
+    ("bisque", "ffe4c4"),
+    ("black", "000000"),
+    ("blanchedalmond", "ffebcd"),
+    ("blue", "0000ff"),
+    ("blueviolet", "8a2be2"),
+    ("brown", "a52a2a"),
+    ("burlywood", "deb887"),
+    ("cadetblue", "5f9ea0"),
+    ("chartreuse", "7fff00"),
+    ("chocolate", "d2691e"),
+    ("coral", "ff7f50"),
+    ("cornflowerblue", "6495ed"),
+    ("cornsilk", "fff8dc"),
+    ("crimson", "dc143c"),
+    ("cyan", "00ffff"),
+    ("darkblue", "00008b"),
+    ("darkcyan", "008b8b"),
+    ("darkgoldenrod", "b8860b"),
+    ("darkgray", "a9a9a9"),
+    ("darkgreen", "006400"),
+    ("darkgrey", "a9a9a9"),
+    ("darkkhaki", "bdb76b"),
+    ("darkmagenta", "8b008b"),
+    ("darkolivegreen", "556b2f"),
+    ("darkorange", "ff8c00"),
+    ("darkorchid", "9932cc"),
+    ("darkred", "8b0000"),
+    ("darksalmon", "e9967a"),
+    ("darkseagreen", "8fbc8f"),
+    ("darkslateblue", "483d8b"),
+    ("darkslategray", "2f4f4f"),
+    ("darkslategrey", "2f4f4f"),
+    ("darkturquoise", "00ced1"),
+    ("darkviolet", "9400d3"),
+    ("deeppink", "ff1493"),
+    ("deepskyblue", "00bfff"),
+    ("dimgray", "696969"),
+    ("dimgrey", "696969"),
+    ("dodgerblue", "1e90ff"),
+    ("firebrick", "b22222"),
+    ("floralwhite", "fffaf0"),
+    ("forestgreen", "228b22"),
+    ("fuchsia", "ff00ff"),
+    ("gainsboro", "dcdcdc"),
+    ("ghostwhite", "f8f8ff"),
+    ("gold", "ffd700"),
+    ("goldenrod", "daa520"),
+    ("gray", "808080"),
+    ("green", "008000"),
+    ("greenyellow", "adff2f"),
+    ("grey", "808080"),
+    ("honeydew", "f0fff0"),
+    ("hotpink", "ff69b4"),
+    ("indianred", "cd5c5c"),
+    ("indigo", "4b0082"),
+    ("ivory", "fffff0"),
+    ("khaki", "f0e68c"),
+    ("lavender", "e6e6fa"),
+    ("lavenderblush", "fff0f5"),
+    ("lawngreen", "7cfc00"),
+    ("lemonchiffon", "fffacd"),
+    ("lightblue", "add8e6"),
+    ("lightcoral", "f08080"),
+    ("lightcyan", "e0ffff"),
+    ("lightgoldenrodyellow", "fafad2"),
+    ("lightgray", "d3d3d3"),
+    ("lightgreen", "90ee90"),
+    ("lightgrey", "d3d3d3"),
+    ("lightpink", "ffb6c1"),
+    ("lightsalmon", "ffa07a"),
+    ("lightseagreen", "20b2aa"),
+    ("lightskyblue", "87cefa"),
+    ("lightslategray", "778899"),
+    ("lightslategrey", "778899"),
+    ("lightsteelblue", "b0c4de"),
+    ("lightyellow", "ffffe0"),
+    ("lime", "00ff00"),
+    ("limegreen", "32cd32"),
+    ("linen", "faf0e6"),
+    ("magenta", "ff00ff"),
+    ("maroon", "800000"),
+    ("mediumaquamarine", "66cdaa"),
+    ("mediumblue", "0000cd"),
+    ("mediumorchid", "ba55d3"),
+    ("mediumpurple", "9370db"),
+    ("mediumseagreen", "3cb371"),
+    ("mediumslateblue", "7b68ee"),
+    ("mediumspringgreen", "00fa9a"),
+    ("mediumturquoise", "48d1cc"),
+    ("mediumvioletred", "c71585"),
+    ("midnightblue", "191970"),
+    ("mintcream", "f5fffa"),
+    ("mistyrose", "ffe4e1"),
+    ("moccasin", "ffe4b5"),
+    ("navajowhite", "ffdead"),
+    ("navy", "000080"),
+    ("oldlace", "fdf5e6"),
+    ("olive", "808000"),
+    ("olivedrab", "6b8e23"),
+    ("orange", "ffa500"),
+    ("orangered", "ff4500"),
+    ("orchid", "da70d6"),
+    ("palegoldenrod", "eee8aa"),
+    ("palegreen", "98fb98"),
+    ("paleturquoise", "afeeee"),
+    ("palevioletred", "db7093"),
+    ("papayawhip", "ffefd5"),
+    ("peachpuff", "ffdab9"),
+    ("peru", "cd853f"),
+    ("pink", "ffc0cb"),
+    ("plum", "dda0dd"),
+    ("powderblue", "b0e0e6"),
+    ("purple", "800080"),
+    ("rebeccapurple", "663399"),
+    ("red", "ff0000"),
+    ("rosybrown", "bc8f8f"),
+    ("royalblue", "4169e1"),
+    ("saddlebrown", "8b4513"),
+    ("salmon", "fa8072"),
+    ("sandybrown", "f4a460"),
+    ("seagreen", "2e8b57"),
+    ("seashell", "fff5ee"),
+    ("sienna", "a0522d"),
+    ("silver", "c0c0c0"),
+    ("skyblue", "87ceeb"),
+    ("slateblue", "6a5acd"),
+    ("slategray", "708090"),
+    ("slategrey", "708090"),
+    ("snow", "fffafa"),
+    ("springgreen", "00ff7f"),
+    ("steelblue", "4682b4"),
+    ("tan", "d2b48c"),
+    ("teal", "008080"),
+    ("thistle", "d8bfd8"),
+    ("tomato", "ff6347"),
+    ("turquoise", "40e0d0"),
+    ("violet", "ee82ee"),
+    ("wheat", "f5deb3"),
+    ("white", "ffffff"),
+    ("whitesmoke", "f5f5f5"),
+    ("yellow", "ffff00"),
+    ("yellowgreen", "9acd32"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Color {
+        s.parse().unwrap_or_else(|e| panic!("{}: {}", s, e))
+    }
+
+    fn close(a: Color, b: Color) -> bool {
+        a.to_array()
+            .iter()
+            .zip(b.to_array())
+            .all(|(a, b)| (a - b).abs() < 0.01)
+    }
+
+    #[test]
+    fn hex_forms() {
+        assert_eq!(parse("#f00"), Color::rgb(1.0, 0.0, 0.0));
+        assert_eq!(parse("#00ff00"), Color::rgb(0.0, 1.0, 0.0));
+        let color = parse("#0000ff80");
+        assert_eq!(color.rgb, [0.0, 0.0, 1.0]);
+        assert!((color.alpha - 128.0 / 255.0).abs() < 1e-6);
+        assert_eq!(parse("#F008").to_string(), "#ff000088");
+        for bad in ["#ff", "#ff00000", "#gg0000", "#"] {
+            assert!(bad.parse::<Color>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn css_names() {
+        assert_eq!(parse("red"), Color::rgb(1.0, 0.0, 0.0));
+        assert_eq!(parse(" Tomato ").to_string(), "#ff6347");
+        assert!("notacolor".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn hsl_forms() {
+        assert!(close(parse("hsl(0, 100%, 50%)"), Color::rgb(1.0, 0.0, 0.0)));
+        assert!(close(parse("hsl(120deg 100% 25%)"), parse("#008000")));
+        let color = parse("hsla(240, 100%, 50%, 0.5)");
+        assert!(close(
+            color,
+            Color {
+                rgb: [0.0, 0.0, 1.0],
+                alpha: 0.5
+            }
+        ));
+        let color = parse("hsl(240 100% 50% / 25%)");
+        assert!((color.alpha - 0.25).abs() < 1e-6);
+        for bad in ["hsl(0, 100%)", "hsl(0, 150%, 50%)", "hsl(0 50 50)"] {
+            assert!(bad.parse::<Color>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn hsl_sweep_stays_in_range() {
+        for h in 0..360 {
+            for s in [0, 25, 50, 75, 100] {
+                for l in 0..=100 {
+                    let text = format!("hsl({}, {}%, {}%)", h, s, l);
+                    assert!(text.parse::<Color>().is_ok(), "{}", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn array_form() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            color: Color,
+        }
+        let parse = |s: &str| toml::from_str::<Wrapper>(s).map(|w| w.color);
+        assert_eq!(
+            parse("color = [1.0, 0.5, 0.0]").ok(),
+            Some(Color::rgb(1.0, 0.5, 0.0))
+        );
+        let color = parse("color = [0.0, 0.0, 0.0, 0.5]").ok();
+        assert_eq!(color.map(|c| c.alpha), Some(0.5));
+        assert!(parse("color = [2.0, 0.0, 0.0]").is_err());
+        assert!(parse("color = [1.0, 0.0]").is_err());
+        assert_eq!(
+            parse("color = \"#ff0000\"").ok(),
+            Some(Color::rgb(1.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn palette_choice() {
+        let palette = [Color::rgb(1.0, 0.0, 0.0), Color::rgb(0.0, 1.0, 0.0)];
+        let choice = |s: &str| s.parse::<ColorChoice>().and_then(|c| c.resolve(&palette));
+        assert_eq!(choice("2"), Ok(palette[1]));
+        assert!(choice("3").is_err());
+        assert!(choice("0").is_err());
+        assert_eq!(choice("blue"), Ok(Color::rgb(0.0, 0.0, 1.0)));
+    }
+}
@@ -1,70 +1,1050 @@
+use crate::bindings::{default_bindings, default_shortcuts, Action};
+use crate::color::Color;
+use crate::migrate;
+use crate::xdg;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+// Пропущенные в файле ключи берутся из Config::default()
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
-    pub thickness: f32,
-    pub color: [f32; 3],
-    pub opacity: f32,
+    // Версия схемы, см. migrate.rs
+    pub version: i64,
     pub ttl: f32,
-    pub fade_start: f32,
-    pub smooth_lines: bool,
-    pub min_point_distance: f32,
-    pub line_feather: f32,
+    // Без значения - три четверти ttl, чтобы файл с одним ttl оставался верным
+    pub fade_start: Option<f32>,
     pub scroll_cooldown: u64,
     pub polling_interval: u64,
+    pub spotlight_dim: f32,
+    pub spotlight_shape: SpotlightShape,
+    pub spotlight_radius: f32,
+    pub spotlight_feather: f32,
+    pub tool: Tool,
+    pub font: String,
+    pub font_size: f32,
+    pub text_background: Option<Color>,
+    pub stamps: Vec<String>,
+    pub stamp_size: f32,
+    pub snap_modifier: String,
+    pub snap_angle: f32,
+    pub snap_grid: f32,
+    pub variable_width: bool,
+    // Без значений - от pen.thickness, чтобы старые конфиги с одной толщиной
+    // рисовали штрихами той же толщины
+    pub min_width: Option<f32>,
+    pub max_width: Option<f32>,
+    pub velocity_scale: f32,
+    pub taper: f32,
+    pub eraser_size: f32,
+    // Выход (wl_output name) под оверлей; без него - первый попавшийся
+    pub output: Option<String>,
+    pub keyboard_interactivity: KeyboardMode,
+    pub reentry: Reentry,
+    pub region_border: Option<Color>,
+    pub indicator: IndicatorStyle,
+    pub indicator_position: ZonePosition,
+    pub indicator_fade: f32,
+    pub pen: Pen,
+    pub renderer: Renderer,
+    pub hot_zones: Vec<HotZone>,
+    pub bindings: BTreeMap<String, Action>,
+    pub shortcuts: BTreeMap<String, Action>,
 }
 
-impl Default for Config {
+// Перо по умолчанию; выбранные на ходу цвет и толщина хранятся в WaylandState
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pen {
+    // Толщина, когда variable_width выключен
+    pub thickness: f32,
+    pub color: Color,
+    pub opacity: f32,
+    // Цвета для cycle-color, клавиш 1-9 и `color <номер>`
+    pub palette: Vec<Color>,
+    // Каждый новый штрих - следующим цветом палитры
+    pub auto_advance: bool,
+}
+
+impl Default for Pen {
     fn default() -> Self {
         Self {
             thickness: 3.0,
-            color: [1.0, 0.0, 0.0],
+            color: PALETTE[0],
             opacity: 0.9,
-            ttl: 2.0,
-            fade_start: 1.5,
+            palette: PALETTE.to_vec(),
+            auto_advance: false,
+        }
+    }
+}
+
+// Обработка штрихов при отрисовке
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Renderer {
+    pub smooth_lines: bool,
+    pub min_point_distance: f32,
+    // Ширина сглаживания края линии, px
+    pub feather: f32,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self {
             smooth_lines: true,
             min_point_distance: 2.0,
-            line_feather: 0.0,
+            feather: 0.0,
+        }
+    }
+}
+
+// Палитра по умолчанию
+const PALETTE: [Color; 6] = [
+    Color::rgb(1.0, 0.0, 0.0),
+    Color::rgb(0.0, 0.8, 0.0),
+    Color::rgb(0.0, 0.4, 1.0),
+    Color::rgb(1.0, 0.85, 0.0),
+    Color::rgb(1.0, 1.0, 1.0),
+    Color::rgb(0.0, 0.0, 0.0),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+    Pen,
+    Text,
+    Stamp,
+    Eraser,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::Pen, Tool::Text, Tool::Stamp, Tool::Eraser];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Pen => "Pen",
+            Tool::Text => "Text",
+            Tool::Stamp => "Stamp",
+            Tool::Eraser => "Eraser",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Tool::Pen => Tool::Text,
+            Tool::Text => Tool::Stamp,
+            Tool::Stamp => Tool::Eraser,
+            Tool::Eraser => Tool::Pen,
+        }
+    }
+}
+
+// Как вернуться из PASSTHROUGH в режим захвата, см. reentry.rs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reentry {
+    Timed,
+    Toggle,
+    Stylus,
+}
+
+// Угол или полоса у края экрана, которая остаётся в input region в PASSTHROUGH
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HotZone {
+    // Имя выхода (wl_output name, например "DP-1"); без него - на любом
+    pub output: Option<String>,
+    pub position: ZonePosition,
+    pub size: i32,
+    pub action: ZoneAction,
+    pub activate: ZoneActivate,
+}
+
+impl HotZone {
+    // (x, y, w, h) на поверхности размером width x height
+    pub fn rect(&self, width: i32, height: i32) -> (i32, i32, i32, i32) {
+        let s = self.size.max(1);
+        match self.position {
+            ZonePosition::TopLeft => (0, 0, s, s),
+            ZonePosition::TopRight => (width - s, 0, s, s),
+            ZonePosition::BottomLeft => (0, height - s, s, s),
+            ZonePosition::BottomRight => (width - s, height - s, s, s),
+            ZonePosition::Top => (0, 0, width, s),
+            ZonePosition::Bottom => (0, height - s, width, s),
+            ZonePosition::Left => (0, 0, s, height),
+            ZonePosition::Right => (width - s, 0, s, height),
+        }
+    }
+
+    pub fn contains(&self, width: i32, height: i32, x: f32, y: f32) -> bool {
+        let (rx, ry, rw, rh) = self.rect(width, height);
+        x >= rx as f32 && x < (rx + rw) as f32 && y >= ry as f32 && y < (ry + rh) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ZonePosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneAction {
+    Capture,
+    Menu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneActivate {
+    Hover,
+    Click,
+}
+
+// Как показывать режим ввода, см. indicator.rs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndicatorStyle {
+    Badge,
+    Border,
+    Dot,
+    None,
+}
+
+// Забирать ли клавиатуру по клику в режиме захвата (нужно для сочетаний клавиш)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyboardMode {
+    OnDemand,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpotlightShape {
+    Circle,
+    Rect,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: migrate::CURRENT,
+            ttl: 2.0,
+            fade_start: None,
             scroll_cooldown: 500,
             polling_interval: 50,
+            spotlight_dim: 0.6,
+            spotlight_shape: SpotlightShape::Circle,
+            spotlight_radius: 150.0,
+            spotlight_feather: 40.0,
+            tool: Tool::Pen,
+            font: "/usr/share/fonts/TTF/DejaVuSans.ttf".to_string(),
+            font_size: 24.0,
+            text_background: None,
+            stamps: vec![
+                "number".to_string(),
+                "✓".to_string(),
+                "✗".to_string(),
+                "★".to_string(),
+            ],
+            stamp_size: 36.0,
+            snap_modifier: "Shift".to_string(),
+            snap_angle: 15.0,
+            snap_grid: 0.0,
+            variable_width: true,
+            min_width: None,
+            max_width: None,
+            velocity_scale: 2500.0,
+            taper: 12.0,
+            eraser_size: 24.0,
+            output: None,
+            keyboard_interactivity: KeyboardMode::OnDemand,
+            reentry: Reentry::Timed,
+            region_border: Some(Color {
+                rgb: [1.0, 1.0, 1.0],
+                alpha: 0.35,
+            }),
+            indicator: IndicatorStyle::Badge,
+            indicator_position: ZonePosition::BottomRight,
+            indicator_fade: 3.0,
+            pen: Pen::default(),
+            renderer: Renderer::default(),
+            hot_zones: Vec::new(),
+            bindings: default_bindings(),
+            shortcuts: default_shortcuts(),
         }
     }
 }
 
 impl Config {
-    fn path() -> PathBuf {
-        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        PathBuf::from(home).join(".config/cherta/default.toml")
+    pub fn fade_start(&self) -> f32 {
+        self.fade_start.unwrap_or(self.ttl * 0.75)
+    }
+
+    pub fn min_width(&self) -> f32 {
+        self.min_width.unwrap_or(self.pen.thickness * 0.5)
+    }
+
+    pub fn max_width(&self) -> f32 {
+        self.max_width.unwrap_or(self.pen.thickness * 5.0 / 3.0)
+    }
+
+    // Ширина штриха: k = 0 - медленно или полный нажим, k = 1 - быстро
+    // или лёгкое касание
+    pub fn stroke_width(&self, k: f32) -> f32 {
+        if !self.variable_width {
+            return self.pen.thickness;
+        }
+        let k = k.clamp(0.0, 1.0);
+        self.max_width() - (self.max_width() - self.min_width()) * k
+    }
+
+    // Каталог пользователя: сюда пишутся конфиг по умолчанию и обновлённые файлы
+    pub fn dir() -> PathBuf {
+        xdg::config_home().join("cherta")
+    }
+
+    // Каталог пользователя, затем системные ($XDG_CONFIG_DIRS, /etc/xdg) -
+    // там общие для всех машин профили и картинки штампов
+    pub fn search_dirs() -> Vec<PathBuf> {
+        search_dirs_in(xdg::config_home(), xdg::config_dirs())
+    }
+
+    pub fn find(file: &str) -> PathBuf {
+        find_in(&Self::search_dirs(), file)
+    }
+
+    pub fn path() -> PathBuf {
+        Self::find("default.toml")
+    }
+
+    pub fn profile_path(name: &str) -> PathBuf {
+        Self::find(&format!("{}.toml", name))
+    }
+
+    // Явно указанный файл: ошибки чтения и разбора возвращаются как есть
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        Self::read_from(path, true)
+    }
+
+    // То же для config show и config validate: старая схема обновляется
+    // только в памяти, на диск ничего не пишется
+    pub fn inspect_from(path: &Path) -> Result<Self, String> {
+        Self::read_from(path, false)
+    }
+
+    fn read_from(path: &Path, write: bool) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (config, _) = Self::parse(path, &data, write)?;
+        let config = config.with_env()?;
+        config
+            .validate()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        eprintln!("[config] loaded from {}", path.display());
+        Ok(config)
+    }
+
+    // Разбор одного файла без проверки значений. Старая схема обновляется
+    // (и при write файл переписывается, см. migrate.rs), незнакомые ключи - только
+    // предупреждение, ошибки - с номером строки и столбца. Вместе с конфигом
+    // возвращается таблица в текущей схеме
+    fn parse(path: &Path, data: &str, write: bool) -> Result<(Self, toml::Table), String> {
+        let mut label = path.display().to_string();
+        let table: toml::Table = data.parse().map_err(|e| describe(&label, data, &e))?;
+        let upgraded = migrate::upgrade(data).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        // Ошибки после обновления - по строкам нового текста: он же теперь
+        // и на диске. Если записать не вышло, так и говорим
+        let (data, table) = match upgraded {
+            Some((version, upgraded)) => {
+                if !write {
+                    label = format!("{} (upgraded in memory)", label);
+                } else if let Err(e) = migrate::save_upgraded(path, data, version, &upgraded) {
+                    eprintln!("[config] {} not upgraded on disk: {}", path.display(), e);
+                    label = format!("{} (upgraded in memory)", label);
+                }
+                let table = upgraded
+                    .parse()
+                    .map_err(|e| describe(&label, &upgraded, &e))?;
+                (upgraded, table)
+            }
+            None => (data.to_string(), table),
+        };
+
+        let (config, unknown) = Self::deserialize_text(&label, &data)?;
+        for key in unknown {
+            eprintln!("[config] {}: unknown key {}, ignored", path.display(), key);
+        }
+        Ok((config, table))
+    }
+
+    // Конфиг из текста и незнакомые ключи в нём ("pen.colour")
+    fn deserialize_text(label: &str, data: &str) -> Result<(Self, Vec<String>), String> {
+        let mut unknown = Vec::new();
+        let config = serde_ignored::deserialize(toml::Deserializer::new(data), |key| {
+            unknown.push(key.to_string())
+        })
+        .map_err(|e| describe(&label, data, &e))?;
+        unknown.retain(|key| key != "inherits");
+        Ok((config, unknown))
+    }
+
+    // Значения, с которыми оверлей работал бы неправильно; все проблемы
+    // сразу, через точку с запятой
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+        let unit = |v: f32| (0.0..=1.0).contains(&v);
+
+        check(self.ttl > 0.0, "ttl must be positive");
+        check(
+            self.fade_start
+                .is_none_or(|fade_start| (0.0..self.ttl).contains(&fade_start)),
+            "fade_start must be at least 0 and less than ttl",
+        );
+        check(
+            self.polling_interval > 0,
+            "polling_interval must be positive",
+        );
+        check(unit(self.spotlight_dim), "spotlight_dim must be in 0..1");
+        check(
+            self.spotlight_radius > 0.0,
+            "spotlight_radius must be positive",
+        );
+        check(
+            self.spotlight_feather >= 0.0,
+            "spotlight_feather must not be negative",
+        );
+        check(self.font_size > 0.0, "font_size must be positive");
+        check(self.stamp_size > 0.0, "stamp_size must be positive");
+        check(
+            (0.0..=90.0).contains(&self.snap_angle),
+            "snap_angle must be in 0..90 degrees",
+        );
+        check(self.snap_grid >= 0.0, "snap_grid must not be negative");
+        check(
+            self.min_width() > 0.0 && self.min_width() <= self.max_width(),
+            "min_width must be positive and not above max_width",
+        );
+        check(self.velocity_scale > 0.0, "velocity_scale must be positive");
+        check(self.taper >= 0.0, "taper must not be negative");
+        check(self.eraser_size > 0.0, "eraser_size must be positive");
+        check(
+            self.indicator_fade >= 0.0,
+            "indicator_fade must not be negative",
+        );
+        check(self.pen.thickness > 0.0, "pen.thickness must be positive");
+        check(
+            !self.pen.palette.is_empty(),
+            "pen.palette must not be empty",
+        );
+        check(unit(self.pen.opacity), "pen.opacity must be in 0..1");
+        check(
+            self.renderer.min_point_distance >= 0.0,
+            "renderer.min_point_distance must not be negative",
+        );
+        check(
+            self.renderer.feather >= 0.0,
+            "renderer.feather must not be negative",
+        );
+        check(
+            self.hot_zones.iter().all(|zone| zone.size > 0),
+            "hot_zones size must be positive",
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    // Профиль задаёт только отличия: <name>.toml накладывается на родителя
+    // (ключ inherits, по умолчанию default), default.toml - на встроенные
+    // значения. Таблицы вроде bindings сливаются по ключам
+    pub fn load_profile(name: &str) -> Result<Self, String> {
+        Self::read_profile(name, true)
+    }
+
+    pub fn inspect_profile(name: &str) -> Result<Self, String> {
+        Self::read_profile(name, false)
     }
 
+    fn read_profile(name: &str, write: bool) -> Result<Self, String> {
+        let table = Self::profile_table(&Self::search_dirs(), name, &mut Vec::new(), write)?;
+        let config: Self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| format!("profile {}: {}", name, e))?;
+        let config = config.with_env()?;
+        config
+            .validate()
+            .map_err(|e| format!("profile {}: {}", name, e))?;
+        eprintln!("[config] loaded profile {}", name);
+        Ok(config)
+    }
+
+    // Файлы, из которых собирается профиль: он сам и цепочка inherits во всех
+    // каталогах поиска - файл пользователя может появиться поверх системного
+    pub fn profile_files(name: &str) -> Vec<PathBuf> {
+        let mut chain: Vec<String> = Vec::new();
+        let mut next = Some(name.to_string());
+        while let Some(name) = next.take() {
+            if chain.contains(&name) {
+                break;
+            }
+            let parent = fs::read_to_string(Self::profile_path(&name))
+                .ok()
+                .and_then(|data| data.parse::<toml::Table>().ok())
+                .and_then(|table| table.get("inherits")?.as_str().map(str::to_string));
+            next = parent.or_else(|| (name != "default").then(|| "default".to_string()));
+            chain.push(name);
+        }
+        let dirs = Self::search_dirs();
+        chain
+            .iter()
+            .flat_map(|name| {
+                dirs.iter()
+                    .map(move |dir| dir.join(format!("{}.toml", name)))
+            })
+            .collect()
+    }
+
+    fn profile_table(
+        dirs: &[PathBuf],
+        name: &str,
+        chain: &mut Vec<String>,
+        write: bool,
+    ) -> Result<toml::Table, String> {
+        if name.is_empty() || name.contains('/') {
+            return Err(format!("bad profile name: {}", name));
+        }
+        if chain.iter().any(|n| n == name) {
+            chain.push(name.to_string());
+            return Err(format!("profile inheritance loop: {}", chain.join(" -> ")));
+        }
+        chain.push(name.to_string());
+
+        let builtin = || match toml::Value::try_from(Self::default()) {
+            Ok(toml::Value::Table(table)) => Ok(table),
+            _ => Err("failed to serialize default config".to_string()),
+        };
+        let path = find_in(dirs, &format!("{}.toml", name));
+        // Профили наследуют и от ещё не созданного default.toml
+        if name == "default" && !path.exists() {
+            return builtin();
+        }
+        let data = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (_, mut table) = Self::parse(&path, &data, write)?;
+
+        let mut base = match table.remove("inherits") {
+            Some(toml::Value::String(parent)) => Self::profile_table(dirs, &parent, chain, write)?,
+            Some(_) => {
+                return Err(format!(
+                    "{}: inherits must be a profile name",
+                    path.display()
+                ))
+            }
+            None if name == "default" => builtin()?,
+            None => Self::profile_table(dirs, "default", chain, write)?,
+        };
+        merge(&mut base, table);
+        Ok(base)
+    }
+
+    // Файл по умолчанию создаётся при первом запуске, если его нет и в
+    // системных каталогах. Если он не читается, работаем со значениями по
+    // умолчанию, но файл пользователя не трогаем
     pub fn load() -> Self {
+        let path = Self::path();
+        let result = if path.exists() {
+            Self::load_from(&path)
+        } else {
+            if let Err(e) = Self::default().save() {
+                eprintln!("[config] failed to create default: {}", e);
+            }
+            Self::default().with_env()
+        };
+        // Переменные CHERTA_* действуют и поверх встроенных значений
+        result.unwrap_or_else(|e| {
+            eprintln!("[config] {}\n[config] using built-in defaults", e);
+            Self::default().with_env().unwrap_or_else(|e| {
+                eprintln!("[config] {}\n[config] ignoring environment overrides", e);
+                Self::default()
+            })
+        })
+    }
+
+    // Как load, но без записи: отсутствующий файл не создаётся
+    pub fn inspect() -> Result<Self, String> {
         let path = Self::path();
         if path.exists() {
-            if let Ok(data) = fs::read_to_string(&path) {
-                if let Ok(config) = toml::from_str(&data) {
-                    eprintln!("[config] loaded from {}", path.display());
-                    return config;
+            Self::inspect_from(&path)
+        } else {
+            Self::default().with_env()
+        }
+    }
+
+    // Переменные CHERTA_<КЛЮЧ> поверх файла: CHERTA_TTL=10,
+    // CHERTA_PEN_COLOR=tomato или короче CHERTA_COLOR=tomato (ключ раздела без
+    // его имени). Значение читается как TOML, а если не читается - как строка
+    fn with_env(self) -> Result<Self, String> {
+        // Не-UTF-8 переменные чужих программ не должны ронять запуск
+        let vars: Vec<(String, String)> = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        self.with_vars(&vars)
+    }
+
+    fn with_vars(self, vars: &[(String, String)]) -> Result<Self, String> {
+        if vars.is_empty() {
+            return Ok(self);
+        }
+        let Ok(toml::Value::Table(mut table)) = toml::Value::try_from(&self) else {
+            return Err("failed to serialize config".to_string());
+        };
+        // Проверяем после каждой переменной, чтобы ошибка называла её
+        let mut config = self;
+        for (name, raw) in vars {
+            let key = name[ENV_PREFIX.len()..].to_ascii_lowercase();
+            let value = format!("value = {}", raw)
+                .parse::<toml::Table>()
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or_else(|| toml::Value::String(raw.clone()));
+            let mut candidate = table.clone();
+            match env_section(&candidate, &key) {
+                Some((section, key)) => {
+                    if let Some(toml::Value::Table(section)) = candidate.get_mut(&section) {
+                        section.insert(key, value);
+                    }
+                }
+                None => {
+                    candidate.insert(key, value);
                 }
             }
+
+            let mut unknown = false;
+            let result: Self =
+                serde_ignored::deserialize(toml::Value::Table(candidate.clone()), |_| {
+                    unknown = true
+                })
+                .map_err(|e| format!("{}: {}", name, e.message()))?;
+            if unknown {
+                eprintln!("[config] {}: unknown setting, ignored", name);
+                continue;
+            }
+            eprintln!("[config] {} set from the environment", name);
+            table = candidate;
+            config = result;
         }
-        let default = Self::default();
-        if let Err(e) = default.save() {
-            eprintln!("[config] failed to create default: {}", e);
-        }
-        default
+        Ok(config)
     }
 
     pub fn save(&self) -> std::io::Result<()> {
-        let path = Self::path();
+        self.save_to(&Self::dir().join("default.toml"))
+    }
+
+    pub fn save_to(&self, path: &Path) -> std::io::Result<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let data = toml::to_string_pretty(self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        fs::write(&path, data)?;
+        let data = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, data)?;
         eprintln!("[config] saved to {}", path.display());
         Ok(())
     }
 }
+
+const ENV_PREFIX: &str = "CHERTA_";
+
+// Раздел и ключ в нём для имени из окружения: "pen_color" и "color" ->
+// ("pen", "color"). None - ключ верхнего уровня
+fn env_section(table: &toml::Table, key: &str) -> Option<(String, String)> {
+    if table.contains_key(key) {
+        return None;
+    }
+    let sections: Vec<(&String, &toml::Table)> = table
+        .iter()
+        .filter_map(|(name, value)| match value {
+            toml::Value::Table(section) => Some((name, section)),
+            _ => None,
+        })
+        .collect();
+    for (name, section) in &sections {
+        if let Some(rest) = key.strip_prefix(&format!("{}_", name)) {
+            if section.contains_key(rest) {
+                return Some((name.to_string(), rest.to_string()));
+            }
+        }
+    }
+    sections
+        .into_iter()
+        .find(|(_, section)| section.contains_key(key))
+        .map(|(name, _)| (name.clone(), key.to_string()))
+}
+
+// "path:3:7: invalid type ..." по позиции ошибки в тексте файла
+fn describe(path: &impl std::fmt::Display, data: &str, error: &toml::de::Error) -> String {
+    let message = error.message().trim_end().replace('\n', ", ");
+    let Some(start) = error.span().map(|span| span.start.min(data.len())) else {
+        return format!("{}: {}", path, message);
+    };
+    let line_start = data[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line = data[..start].matches('\n').count() + 1;
+    let column = data[line_start..start].chars().count() + 1;
+    format!("{}:{}:{}: {}", path, line, column, message)
+}
+
+fn search_dirs_in(config_home: PathBuf, config_dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+    std::iter::once(config_home)
+        .chain(config_dirs)
+        .map(|dir| dir.join("cherta"))
+        .collect()
+}
+
+// Первый найденный файл; если его нет нигде - путь в первом каталоге (у
+// пользователя)
+fn find_in(dirs: &[PathBuf], file: &str) -> PathBuf {
+    dirs.iter()
+        .map(|dir| dir.join(file))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dirs[0].join(file))
+}
+
+// Значения overlay заменяют значения base, вложенные таблицы сливаются
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+
+    fn from_text(data: &str) -> Result<Config, String> {
+        let (config, _) = Config::deserialize_text("test.toml", data)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn partial_file_takes_defaults() {
+        let config = from_text("ttl = 1.0\n").unwrap();
+        assert_eq!(config.ttl, 1.0);
+        assert_eq!(config.fade_start(), 0.75);
+        assert_eq!(config.pen.thickness, Config::default().pen.thickness);
+
+        let config = from_text("[pen]\nopacity = 0.5\n").unwrap();
+        assert_eq!(config.pen.opacity, 0.5);
+        assert_eq!(config.pen.color, Config::default().pen.color);
+        assert_eq!(config.ttl, Config::default().ttl);
+    }
+
+    #[test]
+    fn width_range_follows_thickness() {
+        let config = Config::default();
+        assert_eq!((config.min_width(), config.max_width()), (1.5, 5.0));
+        // Конфиг первой версии с одной толщиной сохраняет её вид
+        let config = from_text("[pen]\nthickness = 6.0\n").unwrap();
+        assert_eq!((config.min_width(), config.max_width()), (3.0, 10.0));
+        let config = from_text("min_width = 2.0\n[pen]\nthickness = 6.0\n").unwrap();
+        assert_eq!((config.min_width(), config.max_width()), (2.0, 10.0));
+        let error = from_text("min_width = 4.0\nmax_width = 2.0\n").unwrap_err();
+        assert!(error.contains("min_width"), "{}", error);
+    }
+
+    #[test]
+    fn explicit_fade_start_is_checked() {
+        assert_eq!(
+            from_text("ttl = 4.0\nfade_start = 1.0\n")
+                .unwrap()
+                .fade_start(),
+            1.0
+        );
+        let error = from_text("ttl = 1.0\nfade_start = 1.5\n").unwrap_err();
+        assert!(error.contains("fade_start"), "{}", error);
+    }
+
+    #[test]
+    fn validation_lists_every_problem() {
+        let error = from_text("ttl = -1.0\n[pen]\nopacity = 2.0\npalette = []\n").unwrap_err();
+        assert_eq!(error.split("; ").count(), 3, "{}", error);
+        assert!(error.contains("ttl must be positive"));
+        assert!(error.contains("pen.opacity"));
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let data = "ttl = 3.0\ncolour = \"red\"\ninherits = \"x\"\n[pen]\nthicknes = 2.0\n";
+        let (config, unknown) = Config::deserialize_text("test.toml", data).unwrap();
+        assert_eq!(config.ttl, 3.0);
+        assert_eq!(unknown, ["colour", "pen.thicknes"]);
+    }
+
+    #[test]
+    fn errors_carry_line_and_column() {
+        let error = from_text("ttl = 2.0\n\n[pen]\nthickness = \"big\"\n").unwrap_err();
+        assert!(
+            error.starts_with("test.toml:4:13: invalid type"),
+            "{}",
+            error
+        );
+        let error = from_text("ttl = \n").unwrap_err();
+        assert!(error.starts_with("test.toml:1:"), "{}", error);
+    }
+
+    fn table(data: &str) -> toml::Table {
+        data.parse().unwrap()
+    }
+
+    #[test]
+    fn merge_overrides_scalars_and_merges_tables() {
+        let mut base =
+            table("ttl = 2.0\nfont = \"Sans\"\n[pen]\nthickness = 3.0\ncolor = \"red\"\n");
+        merge(
+            &mut base,
+            table("ttl = 9.0\n[pen]\ncolor = \"blue\"\n[renderer]\nfeather = 1.0\n"),
+        );
+        assert_eq!(base["ttl"].as_float(), Some(9.0));
+        assert_eq!(base["font"].as_str(), Some("Sans"));
+        assert_eq!(base["pen"]["thickness"].as_float(), Some(3.0));
+        assert_eq!(base["pen"]["color"].as_str(), Some("blue"));
+        assert_eq!(base["renderer"]["feather"].as_float(), Some(1.0));
+    }
+
+    #[test]
+    fn profiles_inherit_in_order() {
+        let dir = temp_dir("config-profiles");
+        fs::write(dir.join("default.toml"), "ttl = 3.0\nfont = \"Serif\"\n").unwrap();
+        fs::write(
+            dir.join("base.toml"),
+            "ttl = 5.0\n[pen]\nthickness = 8.0\ncolor = \"red\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("child.toml"),
+            "inherits = \"base\"\n[pen]\ncolor = \"blue\"\n",
+        )
+        .unwrap();
+
+        let dirs = [dir.clone()];
+        let table = Config::profile_table(&dirs, "child", &mut Vec::new(), true).unwrap();
+        let config: Config = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(config.ttl, 5.0);
+        assert_eq!(config.font, "Serif");
+        assert_eq!(config.pen.thickness, 8.0);
+        assert_eq!(config.pen.color, "blue".parse().unwrap());
+        assert_eq!(config.pen.opacity, Config::default().pen.opacity);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn inheritance_loop_is_an_error() {
+        let dir = temp_dir("config-loop");
+        fs::write(dir.join("a.toml"), "inherits = \"b\"\n").unwrap();
+        fs::write(dir.join("b.toml"), "inherits = \"a\"\n").unwrap();
+        let dirs = [dir.clone()];
+        let error = Config::profile_table(&dirs, "a", &mut Vec::new(), true).unwrap_err();
+        assert_eq!(error, "profile inheritance loop: a -> b -> a");
+        let error = Config::profile_table(&dirs, "../a", &mut Vec::new(), true).unwrap_err();
+        assert!(error.starts_with("bad profile name"), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn with_vars(vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: Vec<(String, String)> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::default().with_vars(&vars)
+    }
+
+    #[test]
+    fn env_sets_top_level_and_section_keys() {
+        let config = with_vars(&[
+            ("CHERTA_TTL", "10"),
+            ("CHERTA_PEN_THICKNESS", "7.5"),
+            ("CHERTA_COLOR", "tomato"),
+            ("CHERTA_RENDERER_SMOOTH_LINES", "false"),
+            ("CHERTA_OUTPUT", "DP-1"),
+            ("CHERTA_STAMPS", "[\"number\", \"★\"]"),
+        ])
+        .unwrap();
+        assert_eq!(config.ttl, 10.0);
+        assert_eq!(config.pen.thickness, 7.5);
+        assert_eq!(config.pen.color, "#ff6347".parse().unwrap());
+        assert!(!config.renderer.smooth_lines);
+        assert_eq!(config.output.as_deref(), Some("DP-1"));
+        assert_eq!(config.stamps, ["number", "★"]);
+    }
+
+    #[test]
+    fn env_errors_name_the_variable() {
+        let error = with_vars(&[("CHERTA_TTL", "abc")]).unwrap_err();
+        assert!(error.starts_with("CHERTA_TTL: invalid type"), "{}", error);
+        let error = with_vars(&[("CHERTA_PEN_COLOR", "notacolor")]).unwrap_err();
+        assert!(
+            error.starts_with("CHERTA_PEN_COLOR: bad color"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn env_unknown_keys_are_skipped() {
+        let config = with_vars(&[
+            ("CHERTA_BOGUS", "1"),
+            ("CHERTA_PEN_BOGUS", "1"),
+            ("CHERTA_TTL", "4"),
+        ])
+        .unwrap();
+        assert_eq!(config.ttl, 4.0);
+    }
+
+    #[test]
+    fn env_section_prefers_exact_names() {
+        let table = match toml::Value::try_from(Config::default()).unwrap() {
+            toml::Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+        assert_eq!(env_section(&table, "ttl"), None);
+        assert_eq!(
+            env_section(&table, "pen_color"),
+            Some(("pen".into(), "color".into()))
+        );
+        assert_eq!(
+            env_section(&table, "color"),
+            Some(("pen".into(), "color".into()))
+        );
+        assert_eq!(
+            env_section(&table, "feather"),
+            Some(("renderer".into(), "feather".into()))
+        );
+        // spotlight_feather - свой ключ верхнего уровня, а не renderer.feather
+        assert_eq!(env_section(&table, "spotlight_feather"), None);
+    }
+
+    #[test]
+    fn find_prefers_the_first_dir() {
+        let user = temp_dir("config-find-user");
+        let system = temp_dir("config-find-system");
+        let dirs = [user.clone(), system.clone()];
+        fs::write(system.join("fleet.toml"), "").unwrap();
+        assert_eq!(find_in(&dirs, "fleet.toml"), system.join("fleet.toml"));
+        fs::write(user.join("fleet.toml"), "").unwrap();
+        assert_eq!(find_in(&dirs, "fleet.toml"), user.join("fleet.toml"));
+        assert_eq!(find_in(&dirs, "none.toml"), user.join("none.toml"));
+        let _ = fs::remove_dir_all(&user);
+        let _ = fs::remove_dir_all(&system);
+    }
+
+    #[test]
+    fn migrated_file_errors_point_into_new_file() {
+        let dir = temp_dir("config-migrated");
+        let path = dir.join("default.toml");
+        let old = "ttl = 5.0\nthickness = \"big\"\n";
+        fs::write(&path, old).unwrap();
+
+        let error = Config::parse(&path, old, true).unwrap_err();
+        let new = fs::read_to_string(&path).unwrap();
+        let line = new.lines().position(|l| l.contains("\"big\"")).unwrap() + 1;
+        let expected = format!("{}:{}:13: invalid type", path.display(), line);
+        assert!(error.starts_with(&expected), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn inspecting_leaves_old_file_alone() {
+        let dir = temp_dir("config-inspect");
+        let path = dir.join("default.toml");
+        let old = "ttl = 5.0\nthickness = 4.0\n";
+        fs::write(&path, old).unwrap();
+
+        let config = Config::inspect_from(&path).unwrap();
+        assert_eq!(config.pen.thickness, 4.0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), old);
+        assert!(!dir.join("default.toml.v1.bak").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn zone(position: ZonePosition, size: i32) -> HotZone {
+        HotZone {
+            output: None,
+            position,
+            size,
+            action: ZoneAction::Capture,
+            activate: ZoneActivate::Hover,
+        }
+    }
+
+    #[test]
+    fn zone_rects() {
+        assert_eq!(
+            zone(ZonePosition::TopLeft, 8).rect(1920, 1080),
+            (0, 0, 8, 8)
+        );
+        assert_eq!(
+            zone(ZonePosition::BottomRight, 8).rect(1920, 1080),
+            (1912, 1072, 8, 8)
+        );
+        assert_eq!(zone(ZonePosition::Top, 4).rect(1920, 1080), (0, 0, 1920, 4));
+        assert_eq!(
+            zone(ZonePosition::Right, 4).rect(1920, 1080),
+            (1916, 0, 4, 1080)
+        );
+        // Нулевой размер - всё равно хотя бы пиксель
+        assert_eq!(
+            zone(ZonePosition::Left, 0).rect(1920, 1080),
+            (0, 0, 1, 1080)
+        );
+    }
+
+    #[test]
+    fn zone_edges() {
+        let corner = zone(ZonePosition::TopRight, 10);
+        assert!(corner.contains(100, 100, 90.0, 0.0));
+        assert!(corner.contains(100, 100, 99.9, 9.9));
+        // Правая и нижняя границы не входят
+        assert!(!corner.contains(100, 100, 89.9, 5.0));
+        assert!(!corner.contains(100, 100, 95.0, 10.0));
+        assert!(!corner.contains(100, 100, 100.0, 5.0));
+        assert!(!corner.contains(100, 100, 95.0, -0.1));
+    }
+
+    #[test]
+    fn search_dirs_put_the_user_first() {
+        assert_eq!(
+            search_dirs_in(
+                PathBuf::from("/cfg"),
+                vec![PathBuf::from("/fleet"), PathBuf::from("/etc/xdg")]
+            ),
+            [
+                PathBuf::from("/cfg/cherta"),
+                PathBuf::from("/fleet/cherta"),
+                PathBuf::from("/etc/xdg/cherta"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,629 @@
+use crate::annotation::Point;
+use crate::bindings::Action;
+use crate::color::{Color, ColorChoice};
+use crate::config::{Config, Tool};
+use crate::export;
+use crate::fsm::{self, Event};
+use crate::wayland::WaylandState;
+use crate::xdg;
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Управляющий сокет $XDG_RUNTIME_DIR/cherta/<WAYLAND_DISPLAY>.sock.
+// Протокол строчный: команда в строке, ответ "ok [...]" или "error: ...".
+// Опрашивается из главного цикла рядом с разбором событий Wayland
+
+pub enum Command {
+    // Любое действие из привязок: "undo", "toggle-spotlight", "color:2"
+    Action(Action),
+    Capture(bool),
+    Visible(bool),
+    Tool(Tool),
+    Color(ColorChoice),
+    Width(f32),
+    Ttl(f32),
+    Export(Option<PathBuf>),
+    // Переключить профиль конфига; без имени - узнать текущий
+    Profile(Option<String>),
+    // Готовый штрих в JSON: {"color":"#ff0000","points":[{x,y,t,w}...]}
+    Stroke(StrokeData),
+}
+
+#[derive(Deserialize)]
+pub struct StrokeData {
+    pub color: Color,
+    pub points: Vec<Point>,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = match line.trim().split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (line.trim(), ""),
+        };
+        if name.is_empty() {
+            return Err("empty command".to_string());
+        }
+        let arg = rest.split_whitespace().next();
+        let number = |arg: Option<&str>| -> Result<f32, String> {
+            let arg = arg.ok_or_else(|| format!("{} needs a value", name))?;
+            arg.parse::<f32>()
+                .ok()
+                .filter(|v| v.is_finite() && *v > 0.0)
+                .ok_or_else(|| format!("bad value: {}", arg))
+        };
+        Ok(match name {
+            "toggle" => Command::Action(Action::TogglePassthrough),
+            "capture" => Command::Capture(true),
+            "release" => Command::Capture(false),
+            "show" => Command::Visible(true),
+            "hide" => Command::Visible(false),
+            "tool" => {
+                let arg = arg.ok_or("tool needs a name")?;
+                Tool::ALL
+                    .into_iter()
+                    .find(|tool| tool.name().eq_ignore_ascii_case(arg))
+                    .map(Command::Tool)
+                    .ok_or_else(|| format!("unknown tool: {}", arg))?
+            }
+            // "hsl(0, 100%, 50%)" с пробелами - тоже остаток строки
+            "color" if rest.is_empty() => return Err("color needs a value".to_string()),
+            "color" => Command::Color(rest.parse()?),
+            "width" => Command::Width(number(arg)?),
+            "ttl" => Command::Ttl(number(arg)?),
+            // Путь может содержать пробелы - берём остаток строки
+            "export" => Command::Export(Some(rest).filter(|r| !r.is_empty()).map(PathBuf::from)),
+            "profile" => Command::Profile(arg.map(str::to_string)),
+            "stroke" => Command::Stroke(
+                serde_json::from_str(rest).map_err(|e| format!("bad stroke: {}", e))?,
+            ),
+            _ => Command::Action(
+                name.parse()
+                    .map_err(|_| format!("unknown command: {}", name))?,
+            ),
+        })
+    }
+}
+
+pub fn execute(state: &mut WaylandState, command: Command) -> Result<String, String> {
+    match command {
+        Command::Action(action) => state.run_action(action),
+        Command::Capture(capture) => {
+            state.set_capturing(capture);
+        }
+        Command::Visible(visible) => {
+            if !fsm::fire(state, if visible { Event::Show } else { Event::Hide })
+                && state.text_draft.is_some()
+            {
+                return Err("finish or cancel the text being typed first".to_string());
+            }
+        }
+        Command::Tool(tool) => state.set_tool(tool),
+        Command::Color(choice) => state.color = choice.resolve(&state.config.pen.palette)?,
+        Command::Width(width) => state.width_scale = width.clamp(0.25, 8.0),
+        Command::Ttl(ttl) => {
+            // Те же правила, что и для файла: fade_start из конфига должен остаться меньше ttl
+            let config = Config {
+                ttl,
+                ..state.config.clone()
+            };
+            config.validate()?;
+            state.config = config;
+        }
+        Command::Stroke(stroke) => state.add_stroke(stroke.color, stroke.points),
+        Command::Profile(None) => return Ok(state.profile.clone()),
+        Command::Profile(Some(name)) => {
+            let config = Config::load_profile(&name)?;
+            state.switch_profile(name, config);
+        }
+        Command::Export(path) => {
+            let path = match path {
+                Some(path) => path,
+                None => default_export_path().map_err(|e| format!("export failed: {}", e))?,
+            };
+            export::write_svg(
+                &path,
+                &state.items,
+                state.width,
+                state.height,
+                &state.config,
+            )
+            .map_err(|e| format!("export failed: {}", e))?;
+            return Ok(path.display().to_string());
+        }
+    }
+    Ok(String::new())
+}
+
+// Снимок без пути - в $XDG_STATE_HOME/cherta
+fn default_export_path() -> std::io::Result<PathBuf> {
+    let dir = xdg::state_home().join("cherta");
+    std::fs::create_dir_all(&dir)?;
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok(dir.join(format!("cherta-{}.svg", secs)))
+}
+
+pub fn socket_path() -> PathBuf {
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+    xdg::runtime_dir()
+        .join("cherta")
+        .join(format!("{}.sock", display))
+}
+
+// Каталог сокета и блокировки, доступный только владельцу. Запасной
+// $TMPDIR/runtime-<uid> мог заранее создать кто-то другой, поэтому и
+// сам каталог, и его родитель проверяются после создания
+pub fn create_runtime_dir() -> std::io::Result<()> {
+    let path = socket_path();
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    check_private(dir)?;
+    match dir.parent() {
+        Some(parent) => check_private(parent),
+        None => Ok(()),
+    }
+}
+
+// Настоящий каталог (не ссылка) наш и закрыт для остальных
+fn check_private(dir: &Path) -> std::io::Result<()> {
+    let meta = std::fs::symlink_metadata(dir)?;
+    let uid = unsafe { libc::getuid() };
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o777 != 0o700 {
+        return Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "{} must be a directory owned by uid {} with mode 0700",
+                dir.display(),
+                uid
+            ),
+        ));
+    }
+    Ok(())
+}
+
+struct Client {
+    stream: UnixStream,
+    buf: Vec<u8>,
+    // Ещё не отправленное: сокет неблокирующий, медленный подписчик не должен
+    // тормозить кадр
+    out: Vec<u8>,
+    subscribed: bool,
+    eof: bool,
+    // Слишком длинная строка: остаток вычитывается и выбрасывается, после
+    // ответа с ошибкой соединение закрывается
+    overflow: bool,
+    closing: bool,
+}
+
+// Подписчик, который столько не вычитал, отключается
+const MAX_BACKLOG: usize = 4 << 20;
+// Самая длинная принимаемая строка команды: с запасом на stroke из
+// десятков тысяч точек (около 40 байт на точку)
+const MAX_LINE: usize = 4 << 20;
+// Сколько читается у клиента за один опрос, остальное ждёт следующего кадра
+const READ_BUDGET: usize = 64 << 10;
+
+fn line_fits(buf: &[u8]) -> bool {
+    match buf.iter().position(|b| *b == b'\n') {
+        Some(end) => end <= MAX_LINE,
+        None => buf.len() <= MAX_LINE,
+    }
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            buf: Vec::new(),
+            out: Vec::new(),
+            subscribed: false,
+            eof: false,
+            overflow: false,
+            closing: false,
+        }
+    }
+
+    // За опрос - не больше READ_BUDGET, длина первой строки проверяется
+    // после чтения
+    fn read(&mut self) -> bool {
+        let mut chunk = [0u8; 4096];
+        let mut budget = READ_BUDGET;
+        while budget > 0 && !self.closing {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    if self.overflow {
+                        self.refuse_line();
+                    }
+                    break;
+                }
+                Ok(n) if self.overflow => {
+                    if chunk[..n].contains(&b'\n') {
+                        self.refuse_line();
+                    }
+                    budget = budget.saturating_sub(n);
+                }
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    budget = budget.saturating_sub(n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return false,
+            }
+        }
+        if !line_fits(&self.buf) {
+            // Строки перед ней уже разобраны: line_fits смотрит на первую
+            let ended = self.buf.contains(&b'\n');
+            self.buf.clear();
+            if ended {
+                self.refuse_line();
+            } else {
+                self.overflow = true;
+            }
+        }
+        true
+    }
+
+    fn refuse_line(&mut self) {
+        self.overflow = false;
+        self.closing = true;
+        self.out.extend_from_slice(b"error: line too long\n");
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        let end = self.buf.iter().position(|b| *b == b'\n')?;
+        let line: Vec<u8> = self.buf.drain(..=end).collect();
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    fn flush(&mut self) -> bool {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    self.out.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return false,
+            }
+        }
+        self.out.len() < MAX_BACKLOG
+    }
+
+    // Подписчик может закрыть свою сторону на запись и дальше только читать
+    fn alive(&self) -> bool {
+        if self.closing {
+            return !self.out.is_empty();
+        }
+        self.subscribed || !self.eof || !self.out.is_empty()
+    }
+}
+
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<Client>,
+}
+
+impl Server {
+    pub fn bind() -> Option<Self> {
+        let path = socket_path();
+        if let Err(e) = create_runtime_dir() {
+            eprintln!(
+                "[control] unsafe or missing socket directory, control socket disabled: {}",
+                e
+            );
+            return None;
+        }
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                eprintln!(
+                    "[control] {} is in use by another instance, control socket disabled",
+                    path.display()
+                );
+                return None;
+            }
+            // Сокет остался от упавшего процесса
+            let _ = std::fs::remove_file(&path);
+        }
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("[control] failed to bind {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if let Err(e) = listener.set_nonblocking(true) {
+            eprintln!("[control] {}", e);
+            return None;
+        }
+        eprintln!("[control] listening on {}", path.display());
+        Some(Self {
+            listener,
+            path,
+            clients: Vec::new(),
+        })
+    }
+
+    // Принимает подключения, выполняет пришедшие целиком строки и рассылает
+    // подписчикам накопившиеся события
+    pub fn poll(&mut self, state: &mut WaylandState) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.clients.push(Client::new(stream));
+            }
+        }
+
+        let events: Vec<String> = state
+            .events
+            .drain(..)
+            .map(|event| event.to_json() + "\n")
+            .collect();
+
+        self.clients.retain_mut(|client| {
+            if !client.read() {
+                return false;
+            }
+            while let Some(line) = client.next_line() {
+                if line.is_empty() {
+                    continue;
+                }
+                let reply = if line == "subscribe" {
+                    client.subscribed = true;
+                    "ok\n".to_string()
+                } else {
+                    match line.parse().and_then(|command| execute(state, command)) {
+                        Ok(info) if info.is_empty() => "ok\n".to_string(),
+                        Ok(info) => format!("ok {}\n", info),
+                        Err(e) => format!("error: {}\n", e),
+                    }
+                };
+                client.out.extend_from_slice(reply.as_bytes());
+            }
+            if client.subscribed && !client.closing {
+                for event in &events {
+                    client.out.extend_from_slice(event.as_bytes());
+                }
+            }
+            client.flush() && client.alive()
+        });
+
+        state.record_events = self.clients.iter().any(|client| client.subscribed);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// `cherta ctl <команда>`: отправить команду запущенному экземпляру.
+// Возвращает код выхода процесса
+pub fn run_client(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("usage: cherta ctl <command> [value]");
+        return 2;
+    }
+    if args[0] == "subscribe" {
+        return subscribe();
+    }
+    match send(&args.join(" ")) {
+        Ok(reply) => {
+            print!("{}", reply);
+            if reply.starts_with("ok") {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("cherta: {}", e);
+            1
+        }
+    }
+}
+
+fn connect() -> Result<UnixStream, String> {
+    let path = socket_path();
+    UnixStream::connect(&path)
+        .map_err(|e| format!("no running instance at {}: {}", path.display(), e))
+}
+
+pub fn send(line: &str) -> Result<String, String> {
+    let mut stream = connect()?;
+    stream
+        .write_all(format!("{}\n", line).as_bytes())
+        .and_then(|_| stream.shutdown(std::net::Shutdown::Write))
+        .map_err(|e| e.to_string())?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|e| e.to_string())?;
+    Ok(reply)
+}
+
+// Печатает события построчно, пока экземпляр не завершится
+fn subscribe() -> i32 {
+    let stream = connect().and_then(|mut stream| {
+        stream
+            .write_all(b"subscribe\n")
+            .map_err(|e| e.to_string())?;
+        Ok(stream)
+    });
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("cherta: {}", e);
+            return 1;
+        }
+    };
+    let mut stdout = std::io::stdout();
+    // Первая строка - "ok" на саму подписку
+    for line in BufReader::new(stream).lines().skip(1) {
+        let Ok(line) = line else {
+            break;
+        };
+        if writeln!(stdout, "{}", line)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn parse(line: &str) -> Command {
+        line.parse().unwrap_or_else(|e| panic!("{}: {}", line, e))
+    }
+
+    fn error(line: &str) -> String {
+        match line.parse::<Command>() {
+            Ok(_) => panic!("{}: parsed", line),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn simple_commands() {
+        assert!(matches!(
+            parse("toggle"),
+            Command::Action(Action::TogglePassthrough)
+        ));
+        assert!(matches!(parse("  capture \n"), Command::Capture(true)));
+        assert!(matches!(parse("release"), Command::Capture(false)));
+        assert!(matches!(parse("show"), Command::Visible(true)));
+        assert!(matches!(parse("hide"), Command::Visible(false)));
+        assert!(matches!(parse("undo"), Command::Action(Action::Undo)));
+        assert!(matches!(
+            parse("color:3"),
+            Command::Action(Action::Color(2))
+        ));
+    }
+
+    #[test]
+    fn commands_with_values() {
+        assert!(matches!(parse("tool Eraser"), Command::Tool(Tool::Eraser)));
+        assert!(matches!(parse("width 2.5"), Command::Width(w) if w == 2.5));
+        assert!(matches!(parse("ttl 30"), Command::Ttl(t) if t == 30.0));
+        assert!(matches!(
+            parse("color 2"),
+            Command::Color(ColorChoice::Palette(1))
+        ));
+        assert!(matches!(
+            parse("color hsl(0, 100%, 50%)"),
+            Command::Color(ColorChoice::Color(c)) if c == Color::rgb(1.0, 0.0, 0.0)
+        ));
+        assert!(matches!(parse("profile"), Command::Profile(None)));
+        assert!(matches!(parse("profile review"), Command::Profile(Some(n)) if n == "review"));
+    }
+
+    #[test]
+    fn export_keeps_spaces_in_path() {
+        assert!(matches!(parse("export"), Command::Export(None)));
+        assert!(matches!(
+            parse("export /tmp/my notes.svg"),
+            Command::Export(Some(p)) if p == Path::new("/tmp/my notes.svg")
+        ));
+    }
+
+    #[test]
+    fn stroke_json() {
+        let line = r##"stroke {"color":"#ff0000","points":[{"x":1,"y":2,"t":0,"w":3}]}"##;
+        let Command::Stroke(stroke) = parse(line) else {
+            panic!("not a stroke");
+        };
+        assert_eq!(stroke.color, Color::rgb(1.0, 0.0, 0.0));
+        assert_eq!(stroke.points.len(), 1);
+        assert_eq!(stroke.points[0].w, 3.0);
+        assert!(error("stroke {\"color\":").starts_with("bad stroke"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error(""), "empty command");
+        assert_eq!(error("   "), "empty command");
+        assert_eq!(error("frobnicate"), "unknown command: frobnicate");
+        assert_eq!(error("tool"), "tool needs a name");
+        assert_eq!(error("tool brush"), "unknown tool: brush");
+        assert_eq!(error("color"), "color needs a value");
+        assert_eq!(error("width"), "width needs a value");
+        assert_eq!(error("width -1"), "bad value: -1");
+        assert_eq!(error("ttl inf"), "bad value: inf");
+        assert!(error("color notacolor").starts_with("bad color"));
+    }
+
+    #[test]
+    fn runtime_dir_must_be_private() {
+        let dir = temp_dir("control-private");
+        let private = dir.join("private");
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&private)
+            .unwrap();
+        check_private(&private).unwrap();
+
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let error = check_private(&private).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o700)).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&private, &link).unwrap();
+        assert!(check_private(&link).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn long_lines_are_refused() {
+        assert!(line_fits(b"toggle\n"));
+        assert!(line_fits(&vec![b'x'; MAX_LINE]));
+        assert!(!line_fits(&vec![b'x'; MAX_LINE + 1]));
+        let mut buf = vec![b'x'; MAX_LINE + 1];
+        buf.push(b'\n');
+        assert!(!line_fits(&buf));
+    }
+
+    #[test]
+    fn long_line_gets_an_error_reply() {
+        let (server, mut peer) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let mut client = Client::new(server);
+        let writer = std::thread::spawn(move || {
+            let line = format!("stroke {}\n", "x".repeat(MAX_LINE));
+            peer.write_all(line.as_bytes()).unwrap();
+            peer.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut reply = String::new();
+            peer.read_to_string(&mut reply).unwrap();
+            reply
+        });
+        while client.flush() && client.alive() {
+            assert!(client.read());
+            assert_eq!(client.next_line(), None);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        drop(client);
+        assert_eq!(writer.join().unwrap(), "error: line too long\n");
+    }
+}
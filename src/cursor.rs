@@ -0,0 +1,187 @@
+use crate::config::Tool;
+use crate::fsm::Mode;
+use crate::wayland::WaylandState;
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsFd, FromRawFd};
+use wayland_client::protocol::{
+    wl_buffer, wl_compositor, wl_pointer, wl_shm, wl_shm_pool, wl_surface,
+};
+use wayland_client::QueueHandle;
+use wayland_cursor::CursorTheme;
+use wayland_protocols::wp::cursor_shape::v1::client::wp_cursor_shape_device_v1::{self, Shape};
+
+// Курсор по режиму и инструменту. Форму берём у композитора через
+// wp_cursor_shape_v1, без него - из темы курсоров (wayland-cursor).
+// У ластика свой курсор: круг размером с ластик в shm-буфере
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorKind {
+    Default,
+    Crosshair,
+    Text,
+    // Горячая зона в PASSTHROUGH
+    Pointer,
+    // Диаметр круга в пикселях
+    Eraser(u32),
+}
+
+impl CursorKind {
+    pub fn for_state(mode: Mode, tool: Tool, eraser_size: f32) -> Self {
+        match mode {
+            Mode::MenuOpen => CursorKind::Default,
+            Mode::Passthrough | Mode::Hidden => CursorKind::Pointer,
+            Mode::Capturing | Mode::Drawing => match tool {
+                Tool::Pen | Tool::Stamp => CursorKind::Crosshair,
+                Tool::Text => CursorKind::Text,
+                Tool::Eraser => CursorKind::Eraser(eraser_size.round().max(8.0) as u32),
+            },
+        }
+    }
+
+    fn shape(self) -> Shape {
+        match self {
+            CursorKind::Default => Shape::Default,
+            CursorKind::Crosshair | CursorKind::Eraser(_) => Shape::Crosshair,
+            CursorKind::Text => Shape::Text,
+            CursorKind::Pointer => Shape::Pointer,
+        }
+    }
+
+    fn theme_name(self) -> &'static str {
+        match self {
+            CursorKind::Default => "default",
+            CursorKind::Crosshair | CursorKind::Eraser(_) => "crosshair",
+            CursorKind::Text => "text",
+            CursorKind::Pointer => "pointer",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Cursors {
+    pub shape_device: Option<wp_cursor_shape_device_v1::WpCursorShapeDeviceV1>,
+    pub theme: Option<CursorTheme>,
+    pub shm: Option<wl_shm::WlShm>,
+    // serial последнего wl_pointer.enter - без него курсор не поставить
+    pub serial: Option<u32>,
+    surface: Option<wl_surface::WlSurface>,
+    eraser: Option<(u32, wl_buffer::WlBuffer)>,
+}
+
+impl Cursors {
+    pub fn apply(
+        &mut self,
+        kind: CursorKind,
+        pointer: &wl_pointer::WlPointer,
+        compositor: &wl_compositor::WlCompositor,
+        qh: &QueueHandle<WaylandState>,
+    ) {
+        let Some(serial) = self.serial else {
+            return;
+        };
+
+        if let CursorKind::Eraser(size) = kind {
+            if self.eraser.as_ref().map(|(s, _)| *s) != Some(size) {
+                if let Some((_, old)) = self.eraser.take() {
+                    old.destroy();
+                }
+                self.eraser = self
+                    .shm
+                    .as_ref()
+                    .and_then(|shm| create_buffer(shm, qh, size, &circle_pixels(size)))
+                    .map(|buffer| (size, buffer));
+            }
+            if let Some((_, buffer)) = &self.eraser {
+                let surface = self
+                    .surface
+                    .get_or_insert_with(|| compositor.create_surface(qh, ()));
+                attach(surface, buffer, size, size);
+                let hotspot = (size / 2) as i32;
+                pointer.set_cursor(serial, Some(surface), hotspot, hotspot);
+                return;
+            }
+        }
+
+        if let Some(device) = &self.shape_device {
+            device.set_shape(serial, kind.shape());
+            return;
+        }
+
+        let Some(theme) = self.theme.as_mut() else {
+            return;
+        };
+        let Some(cursor) = theme.get_cursor(kind.theme_name()) else {
+            return;
+        };
+        let image = &cursor[0];
+        let (width, height) = image.dimensions();
+        let (hx, hy) = image.hotspot();
+        let surface = self
+            .surface
+            .get_or_insert_with(|| compositor.create_surface(qh, ()));
+        attach(surface, image, width, height);
+        pointer.set_cursor(serial, Some(surface), hx as i32, hy as i32);
+    }
+}
+
+fn attach(surface: &wl_surface::WlSurface, buffer: &wl_buffer::WlBuffer, width: u32, height: u32) {
+    surface.attach(Some(buffer), 0, 0);
+    surface.damage_buffer(0, 0, width as i32, height as i32);
+    surface.commit();
+}
+
+// Кольцо: белая линия с тёмной обводкой, видно на любом фоне.
+// ARGB8888 little-endian, альфа premultiplied
+fn circle_pixels(size: u32) -> Vec<u8> {
+    let center = size as f32 / 2.0;
+    let radius = center - 2.0;
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let dx = x as f32 + 0.5 - center;
+            let dy = y as f32 + 0.5 - center;
+            let d = (dx.hypot(dy) - radius).abs();
+            let pixel = if d < 0.75 {
+                [255, 255, 255, 255]
+            } else if d < 1.75 {
+                [0, 0, 0, 160]
+            } else {
+                [0, 0, 0, 0]
+            };
+            pixels.extend_from_slice(&pixel);
+        }
+    }
+    pixels
+}
+
+fn create_buffer(
+    shm: &wl_shm::WlShm,
+    qh: &QueueHandle<WaylandState>,
+    size: u32,
+    pixels: &[u8],
+) -> Option<wl_buffer::WlBuffer> {
+    let fd = unsafe { libc::memfd_create(c"cherta-cursor".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        eprintln!("[cursor] memfd_create failed");
+        return None;
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    if let Err(e) = file.write_all(pixels) {
+        eprintln!("[cursor] failed to fill buffer: {}", e);
+        return None;
+    }
+
+    let pool: wl_shm_pool::WlShmPool = shm.create_pool(file.as_fd(), pixels.len() as i32, qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        size as i32,
+        size as i32,
+        size as i32 * 4,
+        wl_shm::Format::Argb8888,
+        qh,
+        (),
+    );
+    pool.destroy();
+    Some(buffer)
+}
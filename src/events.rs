@@ -0,0 +1,49 @@
+use crate::annotation::Point;
+use crate::color::Color;
+use crate::config::Tool;
+use crate::fsm::Mode;
+use serde::{Deserialize, Serialize};
+
+// События для подписчиков управляющего сокета (команда subscribe),
+// по JSON-объекту в строке: {"event":"mode-changed","mode":"passthrough",...}.
+// Записанный поток читает `cherta replay`
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum OverlayEvent {
+    StrokeStarted {
+        id: u64,
+        color: Color,
+        x: f32,
+        y: f32,
+    },
+    StrokeFinished {
+        id: u64,
+        color: Color,
+        points: Vec<Point>,
+    },
+    // Истёк ttl
+    StrokeExpired {
+        id: u64,
+    },
+    // Стёрт, отменён, очищен или прерван жестом
+    StrokeRemoved {
+        id: u64,
+    },
+    ModeChanged {
+        mode: Mode,
+        from: Mode,
+    },
+    ToolChanged {
+        tool: Tool,
+    },
+    ProfileChanged {
+        profile: String,
+    },
+}
+
+impl OverlayEvent {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
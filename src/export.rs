@@ -0,0 +1,237 @@
+use crate::annotation::{Annotation, Point, StampKind};
+use crate::color::Color;
+use crate::config::Config;
+use crate::geometry::tessellate;
+use crate::renderer::smooth_points;
+use std::fmt::Write;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// Снимок аннотаций в SVG размером с поверхность. Штрихи - залитые контуры той
+// же полосы, что рисует рендерер (переменная ширина и сужение концов
+// сохраняются), текст и штампы - элементы <text>, картинки штампов - ссылки на файл
+
+pub fn svg(items: &[Annotation], width: i32, height: i32, config: &Config) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    );
+    let opacity = config.pen.opacity;
+
+    for item in items {
+        match item {
+            Annotation::Stroke(stroke) => {
+                let outline = outline(&stroke.points, config);
+                if outline.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(
+                    out,
+                    r#"  <path d="M {} Z" fill="{}" fill-opacity="{}"/>"#,
+                    outline.join(" L "),
+                    rgb(stroke.color),
+                    opacity * stroke.color.alpha
+                );
+            }
+            Annotation::Text(label) => {
+                let _ = write!(
+                    out,
+                    r#"  <text x="{:.1}" y="{:.1}" font-family="sans-serif" font-size="{}" fill="{}" fill-opacity="{}" dominant-baseline="hanging">"#,
+                    label.x,
+                    label.y,
+                    config.font_size,
+                    rgb(label.color),
+                    opacity * label.color.alpha
+                );
+                for (i, line) in label.text.split('\n').enumerate() {
+                    let dy = if i == 0 { 0.0 } else { config.font_size * 1.2 };
+                    let _ = write!(
+                        out,
+                        r#"<tspan x="{:.1}" dy="{}">{}</tspan>"#,
+                        label.x,
+                        dy,
+                        escape(line)
+                    );
+                }
+                out.push_str("</text>\n");
+            }
+            Annotation::Stamp(stamp) => {
+                let size = config.stamp_size;
+                match &stamp.kind {
+                    StampKind::Number(n) => {
+                        let _ = writeln!(
+                            out,
+                            r#"  <circle cx="{:.1}" cy="{:.1}" r="{}" fill="{}" fill-opacity="{}"/>"#,
+                            stamp.x,
+                            stamp.y,
+                            size / 2.0,
+                            rgb(stamp.color),
+                            opacity * stamp.color.alpha
+                        );
+                        centered_text(
+                            &mut out,
+                            &n.to_string(),
+                            stamp.x,
+                            stamp.y,
+                            config,
+                            Color::rgb(1.0, 1.0, 1.0),
+                        );
+                    }
+                    StampKind::Symbol(symbol) => {
+                        centered_text(&mut out, symbol, stamp.x, stamp.y, config, stamp.color);
+                    }
+                    StampKind::Image(name) => {
+                        let path = Config::find(name);
+                        let _ = writeln!(
+                            out,
+                            r#"  <image href="{}" x="{:.1}" y="{:.1}" width="{s}" height="{s}" opacity="{}"/>"#,
+                            escape(&file_uri(&path)),
+                            stamp.x - size / 2.0,
+                            stamp.y - size / 2.0,
+                            opacity,
+                            s = size
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+pub fn write_svg(
+    path: &Path,
+    items: &[Annotation],
+    width: i32,
+    height: i32,
+    config: &Config,
+) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, svg(items, width, height, config))
+}
+
+// Полоса треугольников из tessellate идёт парами (левый, правый край):
+// контур - левый край от начала, затем правый от конца
+fn outline(points: &[Point], config: &Config) -> Vec<String> {
+    let points = if config.renderer.smooth_lines {
+        smooth_points(points, config.renderer.min_point_distance)
+    } else {
+        points.to_vec()
+    };
+    let strip = tessellate(&points, config.taper);
+    let left = strip.iter().step_by(2);
+    let right = strip.iter().skip(1).step_by(2).rev();
+    left.chain(right)
+        .map(|(x, y, _)| format!("{:.1},{:.1}", x, y))
+        .collect()
+}
+
+fn centered_text(out: &mut String, text: &str, x: f32, y: f32, config: &Config, fill: Color) {
+    let _ = writeln!(
+        out,
+        r#"  <text x="{:.1}" y="{:.1}" font-family="sans-serif" font-size="{}" fill="{}" fill-opacity="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+        x,
+        y,
+        config.font_size,
+        rgb(fill),
+        config.pen.opacity * fill.alpha,
+        escape(text)
+    );
+}
+
+// Цвет без альфы: прозрачность уходит в *-opacity
+fn rgb(color: Color) -> String {
+    Color::rgb(color.rgb[0], color.rgb[1], color.rgb[2]).to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// file:// URI: всё, кроме безопасных символов и '/', - через %XX, байт за байтом
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            let _ = write!(uri, "%{:02X}", b);
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::{Stamp, Stroke};
+
+    #[test]
+    fn file_uri_encodes_special_characters() {
+        assert_eq!(file_uri(Path::new("/a/b.png")), "file:///a/b.png");
+        assert_eq!(
+            file_uri(Path::new("/my dir/#1 & \"q\" <x>.png")),
+            "file:///my%20dir/%231%20%26%20%22q%22%20%3Cx%3E.png"
+        );
+        assert_eq!(
+            file_uri(Path::new("/звезда.png")),
+            "file:///%D0%B7%D0%B2%D0%B5%D0%B7%D0%B4%D0%B0.png"
+        );
+    }
+
+    #[test]
+    fn image_stamp_href_is_valid() {
+        let stamp = Stamp {
+            x: 10.0,
+            y: 10.0,
+            kind: StampKind::Image("/tmp/a b&c.png".to_string()),
+            color: Color::rgb(1.0, 0.0, 0.0),
+            t: 0.0,
+        };
+        let svg = svg(&[Annotation::Stamp(stamp)], 100, 100, &Config::default());
+        assert!(
+            svg.contains(r#"href="file:///tmp/a%20b%26c.png""#),
+            "{}",
+            svg
+        );
+    }
+
+    #[test]
+    fn strokes_keep_their_width() {
+        let point = |x: f32, w: f32| Point {
+            x,
+            y: 0.0,
+            t: 0.0,
+            w,
+        };
+        let stroke = Stroke {
+            id: 1,
+            points: vec![point(0.0, 2.0), point(10.0, 2.0), point(20.0, 6.0)],
+            color: Color::rgb(1.0, 0.0, 0.0),
+        };
+        let mut config = Config {
+            taper: 0.0,
+            ..Config::default()
+        };
+        config.renderer.smooth_lines = false;
+        let svg = svg(&[Annotation::Stroke(stroke)], 100, 100, &config);
+        assert!(
+            svg.contains(
+                r#"<path d="M 0.0,1.0 L 10.0,1.0 L 20.0,3.0 L 20.0,-3.0 L 10.0,-1.0 L 0.0,-1.0 Z""#
+            ),
+            "{}",
+            svg
+        );
+    }
+}
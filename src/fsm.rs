@@ -0,0 +1,268 @@
+// Конечный автомат режима ввода. Сам автомат ничего не знает о Wayland:
+// побочные эффекты (input region, фокус клавиатуры, курсор, индикатор)
+// выполняет хозяин через Hooks при входе и выходе из состояний
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    Capturing,   // ввод наш, ничего не рисуется
+    Drawing,     // идёт хотя бы один штрих или стирание
+    Passthrough, // ввод уходит окнам под оверлеем (кроме горячих зон)
+    Hidden,      // оверлей скрыт и прозрачен для ввода
+    MenuOpen,    // открыто меню инструментов
+}
+
+impl Mode {
+    pub fn captures_input(self) -> bool {
+        matches!(self, Mode::Capturing | Mode::Drawing | Mode::MenuOpen)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Capture,
+    Release,
+    StrokeStart,
+    StrokeEnd,
+    OpenMenu,
+    CloseMenu,
+    Hide,
+    Show,
+}
+
+// Условия, от которых зависят переходы; хозяин собирает их перед событием
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Guards {
+    pub editing_text: bool,
+    pub active_strokes: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Guard {
+    Always,
+    // Недописанный текст держит клавиатуру - не отпускаем ввод под ним
+    NotEditingText,
+    // Мультитач: Drawing, пока не закончился последний штрих
+    NoActiveStrokes,
+}
+
+impl Guard {
+    fn allows(self, guards: &Guards) -> bool {
+        match self {
+            Guard::Always => true,
+            Guard::NotEditingText => !guards.editing_text,
+            Guard::NoActiveStrokes => !guards.active_strokes,
+        }
+    }
+}
+
+use Mode::*;
+
+const VISIBLE: &[Mode] = &[Capturing, Drawing, Passthrough, MenuOpen];
+
+const TRANSITIONS: &[(&[Mode], Event, Mode, Guard)] = &[
+    (&[Passthrough], Event::Capture, Capturing, Guard::Always),
+    (
+        &[Capturing, Drawing, MenuOpen],
+        Event::Release,
+        Passthrough,
+        Guard::NotEditingText,
+    ),
+    (&[Capturing], Event::StrokeStart, Drawing, Guard::Always),
+    (
+        &[Drawing],
+        Event::StrokeEnd,
+        Capturing,
+        Guard::NoActiveStrokes,
+    ),
+    (
+        &[Capturing, Passthrough],
+        Event::OpenMenu,
+        MenuOpen,
+        Guard::Always,
+    ),
+    (&[MenuOpen], Event::CloseMenu, Capturing, Guard::Always),
+    // Черновик текста держит клавиатуру - скрытый оверлей не должен её забирать
+    (VISIBLE, Event::Hide, Hidden, Guard::NotEditingText),
+    (&[Hidden], Event::Show, Capturing, Guard::Always),
+];
+
+pub struct Fsm {
+    mode: Mode,
+}
+
+impl Fsm {
+    pub fn new() -> Self {
+        Self { mode: Capturing }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // Куда ведёт событие из текущего состояния; None - перехода нет
+    pub fn next(&self, event: Event, guards: &Guards) -> Option<Mode> {
+        TRANSITIONS
+            .iter()
+            .find(|(from, e, _, guard)| {
+                *e == event && from.contains(&self.mode) && guard.allows(guards)
+            })
+            .map(|(_, _, to, _)| *to)
+    }
+}
+
+pub trait Hooks {
+    fn fsm(&mut self) -> &mut Fsm;
+    fn guards(&self) -> Guards;
+
+    fn on_exit(&mut self, _from: Mode, _to: Mode) {}
+    fn on_enter(&mut self, _to: Mode, _from: Mode) {}
+}
+
+// Подаёт событие автомату хозяина; true, если переход состоялся
+pub fn fire<H: Hooks>(host: &mut H, event: Event) -> bool {
+    let guards = host.guards();
+    let fsm = host.fsm();
+    let from = fsm.mode;
+    let Some(to) = fsm.next(event, &guards) else {
+        return false;
+    };
+    host.on_exit(from, to);
+    host.fsm().mode = to;
+    host.on_enter(to, from);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Host {
+        fsm: Option<Fsm>,
+        guards: Guards,
+        log: Vec<String>,
+    }
+
+    impl Hooks for Host {
+        fn fsm(&mut self) -> &mut Fsm {
+            self.fsm.get_or_insert_with(Fsm::new)
+        }
+
+        fn guards(&self) -> Guards {
+            self.guards
+        }
+
+        fn on_exit(&mut self, from: Mode, to: Mode) {
+            self.log.push(format!("exit {:?} -> {:?}", from, to));
+        }
+
+        fn on_enter(&mut self, to: Mode, from: Mode) {
+            self.log.push(format!("enter {:?} <- {:?}", to, from));
+        }
+    }
+
+    fn mode(host: &mut Host) -> Mode {
+        host.fsm().mode()
+    }
+
+    #[test]
+    fn starts_capturing() {
+        assert_eq!(Fsm::new().mode(), Capturing);
+    }
+
+    #[test]
+    fn release_and_capture_round_trip() {
+        let mut host = Host::default();
+        assert!(fire(&mut host, Event::Release));
+        assert_eq!(mode(&mut host), Passthrough);
+        assert!(fire(&mut host, Event::Capture));
+        assert_eq!(mode(&mut host), Capturing);
+    }
+
+    #[test]
+    fn hooks_run_exit_before_enter() {
+        let mut host = Host::default();
+        fire(&mut host, Event::Release);
+        assert_eq!(
+            host.log,
+            [
+                "exit Capturing -> Passthrough",
+                "enter Passthrough <- Capturing"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejected_event_runs_no_hooks() {
+        let mut host = Host::default();
+        assert!(!fire(&mut host, Event::Capture));
+        assert!(!fire(&mut host, Event::Show));
+        assert!(host.log.is_empty());
+        assert_eq!(mode(&mut host), Capturing);
+    }
+
+    #[test]
+    fn drawing_lasts_until_last_stroke() {
+        let mut host = Host::default();
+        fire(&mut host, Event::StrokeStart);
+        assert_eq!(mode(&mut host), Drawing);
+
+        host.guards.active_strokes = true;
+        assert!(!fire(&mut host, Event::StrokeEnd));
+        assert_eq!(mode(&mut host), Drawing);
+
+        host.guards.active_strokes = false;
+        assert!(fire(&mut host, Event::StrokeEnd));
+        assert_eq!(mode(&mut host), Capturing);
+    }
+
+    #[test]
+    fn text_editing_blocks_release() {
+        let mut host = Host::default();
+        host.guards.editing_text = true;
+        assert!(!fire(&mut host, Event::Release));
+        assert_eq!(mode(&mut host), Capturing);
+    }
+
+    #[test]
+    fn text_editing_blocks_hide() {
+        let mut host = Host::default();
+        host.guards.editing_text = true;
+        assert!(!fire(&mut host, Event::Hide));
+        assert_eq!(mode(&mut host), Capturing);
+        host.guards.editing_text = false;
+        assert!(fire(&mut host, Event::Hide));
+        assert_eq!(mode(&mut host), Hidden);
+    }
+
+    #[test]
+    fn menu_opens_from_passthrough_and_closes_to_capturing() {
+        let mut host = Host::default();
+        fire(&mut host, Event::Release);
+        assert!(fire(&mut host, Event::OpenMenu));
+        assert_eq!(mode(&mut host), MenuOpen);
+        assert!(mode(&mut host).captures_input());
+        assert!(fire(&mut host, Event::CloseMenu));
+        assert_eq!(mode(&mut host), Capturing);
+    }
+
+    #[test]
+    fn hidden_ignores_everything_but_show() {
+        let mut host = Host::default();
+        assert!(fire(&mut host, Event::Hide));
+        for event in [
+            Event::Capture,
+            Event::Release,
+            Event::StrokeStart,
+            Event::OpenMenu,
+            Event::Hide,
+        ] {
+            assert!(!fire(&mut host, event), "{:?}", event);
+        }
+        assert!(fire(&mut host, Event::Show));
+        assert_eq!(mode(&mut host), Capturing);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::annotation::Point;
+
+// Привязка к сетке и выравнивание угла для прямых отрезков
+
+pub fn snap_to_grid(p: (f32, f32), grid: f32) -> (f32, f32) {
+    if grid <= 0.0 {
+        return p;
+    }
+    ((p.0 / grid).round() * grid, (p.1 / grid).round() * grid)
+}
+
+// Отрезок из start в сторону end: угол кратен angle_step (в градусах),
+// длина кратна шагу сетки, если он задан
+pub fn snap_segment(
+    start: (f32, f32),
+    end: (f32, f32),
+    angle_step: f32,
+    grid: f32,
+) -> ((f32, f32), (f32, f32)) {
+    let start = snap_to_grid(start, grid);
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let mut length = (dx * dx + dy * dy).sqrt();
+    let mut angle = dy.atan2(dx);
+
+    if angle_step > 0.0 {
+        let step = angle_step.to_radians();
+        angle = (angle / step).round() * step;
+    }
+    if grid > 0.0 {
+        length = (length / grid).round() * grid;
+    }
+
+    (
+        start,
+        (
+            start.0 + length * angle.cos(),
+            start.1 + length * angle.sin(),
+        ),
+    )
+}
+
+// Полоса треугольников (x, y, t0) вдоль штриха: ширина интерполируется
+// между точками, на длине taper от концов штрих сужается
+pub fn tessellate(points: &[Point], taper: f32) -> Vec<(f32, f32, f32)> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut distances = Vec::with_capacity(points.len());
+    let mut total = 0.0;
+    distances.push(0.0);
+    for pair in points.windows(2) {
+        total += ((pair[1].x - pair[0].x).powi(2) + (pair[1].y - pair[0].y).powi(2)).sqrt();
+        distances.push(total);
+    }
+    let taper = taper.min(total / 2.0);
+
+    let mut vertices = Vec::with_capacity(points.len() * 2);
+    for (i, p) in points.iter().enumerate() {
+        let prev = points[i.saturating_sub(1)];
+        let next = points[(i + 1).min(points.len() - 1)];
+        let (dx, dy) = (next.x - prev.x, next.y - prev.y);
+        let len = (dx * dx + dy * dy).sqrt();
+        let (nx, ny) = if len > 0.0 {
+            (-dy / len, dx / len)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let mut half = p.w / 2.0;
+        if taper > 0.0 {
+            let edge = distances[i].min(total - distances[i]);
+            half *= (edge / taper).clamp(0.1, 1.0);
+        }
+
+        vertices.push((p.x + nx * half, p.y + ny * half, p.t));
+        vertices.push((p.x - nx * half, p.y - ny * half, p.t));
+    }
+    vertices
+}
+
+// Задевает ли круг прямоугольник [x0, y0, x1, y1]: ближайшая к центру
+// точка прямоугольника не дальше радиуса
+pub fn circle_hits_rect(center: (f32, f32), r: f32, rect: [f32; 4]) -> bool {
+    let [x0, y0, x1, y1] = rect;
+    let dx = center.0 - center.0.clamp(x0, x1);
+    let dy = center.1 - center.1.clamp(y0, y1);
+    dx * dx + dy * dy <= r * r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    // Конец отрезка длины 100 под углом в градусах
+    fn towards(degrees: f32) -> (f32, f32) {
+        let a = degrees.to_radians();
+        (100.0 * a.cos(), 100.0 * a.sin())
+    }
+
+    #[test]
+    fn snaps_to_nearest_angle() {
+        let (_, end) = snap_segment((0.0, 0.0), towards(10.0), 45.0, 0.0);
+        assert!(close(end, (100.0, 0.0)), "{:?}", end);
+        let (_, end) = snap_segment((0.0, 0.0), towards(40.0), 45.0, 0.0);
+        assert!(close(end, towards(45.0)), "{:?}", end);
+        let (_, end) = snap_segment((0.0, 0.0), towards(-80.0), 45.0, 0.0);
+        assert!(close(end, (0.0, -100.0)), "{:?}", end);
+        let (_, end) = snap_segment((0.0, 0.0), towards(175.0), 15.0, 0.0);
+        assert!(close(end, (-100.0, 0.0)), "{:?}", end);
+    }
+
+    #[test]
+    fn exact_angles_and_boundaries() {
+        for degrees in [0.0, 45.0, 90.0, 135.0, 180.0, -45.0] {
+            let (_, end) = snap_segment((0.0, 0.0), towards(degrees), 45.0, 0.0);
+            assert!(close(end, towards(degrees)), "{}: {:?}", degrees, end);
+        }
+        // Граница между 0° и 45° - 22.5°
+        let (_, end) = snap_segment((0.0, 0.0), towards(22.0), 45.0, 0.0);
+        assert!(close(end, towards(0.0)), "{:?}", end);
+        let (_, end) = snap_segment((0.0, 0.0), towards(23.0), 45.0, 0.0);
+        assert!(close(end, towards(45.0)), "{:?}", end);
+    }
+
+    #[test]
+    fn zero_step_keeps_angle_and_length() {
+        let (start, end) = snap_segment((1.0, 2.0), (31.0, 42.0), 0.0, 0.0);
+        assert_eq!(start, (1.0, 2.0));
+        assert!(close(end, (31.0, 42.0)), "{:?}", end);
+    }
+
+    #[test]
+    fn zero_length_segment() {
+        let (start, end) = snap_segment((5.0, 5.0), (5.0, 5.0), 45.0, 0.0);
+        assert_eq!(start, (5.0, 5.0));
+        assert!(close(end, (5.0, 5.0)), "{:?}", end);
+        assert!(end.0.is_finite() && end.1.is_finite());
+        let (start, end) = snap_segment((12.0, 9.0), (13.0, 11.0), 45.0, 10.0);
+        assert_eq!(start, (10.0, 10.0));
+        assert!(close(end, start), "{:?}", end);
+    }
+
+    #[test]
+    fn grid_snaps_start_and_length() {
+        assert_eq!(snap_to_grid((14.0, 26.0), 10.0), (10.0, 30.0));
+        assert_eq!(snap_to_grid((14.0, 26.0), 0.0), (14.0, 26.0));
+        let (start, end) = snap_segment((2.0, 3.0), (47.0, 1.0), 45.0, 10.0);
+        assert_eq!(start, (0.0, 0.0));
+        assert!(close(end, (50.0, 0.0)), "{:?}", end);
+    }
+
+    fn point(x: f32, y: f32, w: f32) -> Point {
+        Point { x, y, t: x, w }
+    }
+
+    #[test]
+    fn straight_line_is_an_even_band() {
+        let points = [
+            point(0.0, 0.0, 4.0),
+            point(10.0, 0.0, 4.0),
+            point(20.0, 0.0, 4.0),
+        ];
+        let vertices = tessellate(&points, 0.0);
+        assert_eq!(vertices.len(), 6);
+        for (i, pair) in vertices.chunks(2).enumerate() {
+            let x = i as f32 * 10.0;
+            assert_eq!(pair[0], (x, 2.0, x));
+            assert_eq!(pair[1], (x, -2.0, x));
+        }
+    }
+
+    #[test]
+    fn width_follows_points() {
+        let points = [point(0.0, 0.0, 2.0), point(0.0, 10.0, 6.0)];
+        let vertices = tessellate(&points, 0.0);
+        // Вдоль оси y нормаль смотрит в -x
+        assert_eq!(
+            vertices,
+            [
+                (-1.0, 0.0, 0.0),
+                (1.0, 0.0, 0.0),
+                (-3.0, 10.0, 0.0),
+                (3.0, 10.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn taper_narrows_the_ends() {
+        let points: Vec<Point> = (0..=10)
+            .map(|i| point(i as f32 * 10.0, 0.0, 10.0))
+            .collect();
+        let vertices = tessellate(&points, 20.0);
+        let half = |i: usize| vertices[i * 2].1;
+        assert_eq!(half(0), 0.5);
+        assert_eq!(half(1), 2.5);
+        assert_eq!(half(5), 5.0);
+        assert_eq!(half(10), 0.5);
+    }
+
+    #[test]
+    fn degenerate_strokes() {
+        assert!(tessellate(&[], 0.0).is_empty());
+        assert!(tessellate(&[point(5.0, 5.0, 3.0)], 0.0).is_empty());
+        // Две точки в одном месте: нормали нет, полоса вырождается в точку
+        let vertices = tessellate(&[point(5.0, 5.0, 3.0), point(5.0, 5.0, 3.0)], 10.0);
+        assert_eq!(vertices.len(), 4);
+        assert!(vertices.iter().all(|v| v.0 == 5.0 && v.1 == 5.0));
+    }
+
+    #[test]
+    fn circle_against_rect() {
+        let rect = [10.0, 10.0, 110.0, 30.0];
+        // Внутри, в том числе далеко от левого верхнего угла
+        assert!(circle_hits_rect((100.0, 25.0), 1.0, rect));
+        // Рядом со стороной и с углом
+        assert!(circle_hits_rect((115.0, 20.0), 5.0, rect));
+        assert!(!circle_hits_rect((116.0, 20.0), 5.0, rect));
+        assert!(circle_hits_rect((113.0, 34.0), 5.0, rect));
+        assert!(!circle_hits_rect((114.0, 35.0), 5.0, rect));
+        // Вырожденный прямоугольник - точка
+        assert!(circle_hits_rect((3.0, 4.0), 5.0, [0.0, 0.0, 0.0, 0.0]));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::color::Color;
+use crate::config::{Config, IndicatorStyle, Tool, ZonePosition};
+use crate::renderer::rgba;
+use crate::text::TextRenderer;
+use crate::wayland::WaylandState;
+
+// Индикатор режима: куда уйдёт следующий клик - в оверлей или в окно под ним,
+// плюс текущие инструмент и цвет. После изменения виден indicator_fade секунд
+
+const MARGIN: f32 = 12.0;
+const PADDING: f32 = 8.0;
+const SWATCH: f32 = 12.0;
+const DOT_OFFSET: f32 = 20.0;
+const FADE_OUT: f32 = 0.5;
+const PASSTHROUGH_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+
+#[derive(Clone, Copy, PartialEq)]
+struct Shown {
+    capturing: bool,
+    tool: Tool,
+    color: Color,
+}
+
+pub struct Indicator {
+    shown: Option<Shown>,
+    changed: f32,
+}
+
+impl Indicator {
+    pub fn new() -> Self {
+        Self {
+            shown: None,
+            changed: 0.0,
+        }
+    }
+
+    // 1 сразу после изменения, затем за FADE_OUT секунд до нуля
+    fn alpha(&mut self, shown: Shown, now: f32, fade: f32) -> f32 {
+        if self.shown != Some(shown) {
+            self.shown = Some(shown);
+            self.changed = now;
+        }
+        if fade <= 0.0 {
+            return 1.0;
+        }
+        (1.0 - (now - self.changed - fade) / FADE_OUT).clamp(0.0, 1.0)
+    }
+
+    pub fn draw(&mut self, text: &mut TextRenderer, state: &WaylandState, now: f32) {
+        let config = &state.config;
+        if config.indicator == IndicatorStyle::None {
+            return;
+        }
+        let shown = Shown {
+            capturing: state.fsm.mode().captures_input(),
+            tool: state.tool,
+            color: state.color,
+        };
+        let alpha = self.alpha(shown, now, config.indicator_fade);
+        if alpha <= 0.0 {
+            return;
+        }
+
+        let mode_color = if shown.capturing {
+            shown.color
+        } else {
+            PASSTHROUGH_COLOR
+        };
+        let fg = [1.0, 1.0, 1.0, alpha];
+        let (w, h) = (state.width as f32, state.height as f32);
+
+        text.begin(now, config);
+        match config.indicator {
+            IndicatorStyle::Badge => {
+                let mode = if shown.capturing {
+                    "Capture"
+                } else {
+                    "Passthrough"
+                };
+                let label = format!("{} · {}", mode, shown.tool.name());
+                let (tw, th) = text.measure(&label);
+                let size = (SWATCH + PADDING * 3.0 + tw, th + PADDING * 2.0);
+                let [x0, y0, x1, y1] = badge_rect(config, w, h, size);
+                text.draw_rect([x0, y0, x1, y1], [0.1, 0.1, 0.1, 0.8 * alpha]);
+
+                let cy = (y0 + y1) / 2.0;
+                let swatch = [x0 + PADDING, cy - SWATCH / 2.0];
+                text.draw_rect(
+                    [swatch[0], swatch[1], swatch[0] + SWATCH, swatch[1] + SWATCH],
+                    rgba(shown.color, alpha),
+                );
+                // Полоска цвета режима сверху: цвет кисти или серый в PASSTHROUGH
+                text.draw_rect([x0, y0, x1, y0 + 2.0], rgba(mode_color, alpha));
+                let cx = x0 + SWATCH + PADDING * 2.0 + tw / 2.0;
+                text.draw_centered(&label, (cx, cy), now, fg);
+            }
+            IndicatorStyle::Border => {
+                let width = if shown.capturing { 3.0 } else { 1.0 };
+                text.draw_frame(
+                    [width, width, w - width, h - width],
+                    width,
+                    rgba(mode_color, alpha),
+                );
+                let (tw, th) = text.measure(shown.tool.name());
+                let rect = [
+                    (w - tw) / 2.0 - PADDING,
+                    width,
+                    (w + tw) / 2.0 + PADDING,
+                    width + th + PADDING,
+                ];
+                text.draw_rect(rect, rgba(mode_color, 0.8 * alpha));
+                let center = (w / 2.0, (rect[1] + rect[3]) / 2.0);
+                text.draw_centered(shown.tool.name(), center, now, fg);
+            }
+            IndicatorStyle::Dot => {
+                // В PASSTHROUGH события не приходят - точка остаётся там,
+                // где указатель был последний раз
+                let (x, y) = state.pointer_pos;
+                let center = (x + DOT_OFFSET, y + DOT_OFFSET);
+                let dot = if shown.capturing { "●" } else { "○" };
+                text.draw_centered(dot, center, now, rgba(mode_color, alpha));
+                let initial = &shown.tool.name()[..1];
+                let (dw, _) = text.measure(dot);
+                text.draw_centered(initial, (center.0 + dw, center.1), now, fg);
+            }
+            IndicatorStyle::None => {}
+        }
+    }
+}
+
+// Плашка у края или в углу экрана; size - (ширина, высота)
+fn badge_rect(config: &Config, w: f32, h: f32, size: (f32, f32)) -> [f32; 4] {
+    let (bw, bh) = size;
+    let left = MARGIN;
+    let right = w - MARGIN - bw;
+    let top = MARGIN;
+    let bottom = h - MARGIN - bh;
+    let (cx, cy) = ((w - bw) / 2.0, (h - bh) / 2.0);
+    let (x, y) = match config.indicator_position {
+        ZonePosition::TopLeft => (left, top),
+        ZonePosition::TopRight => (right, top),
+        ZonePosition::BottomLeft => (left, bottom),
+        ZonePosition::BottomRight => (right, bottom),
+        ZonePosition::Top => (cx, top),
+        ZonePosition::Bottom => (cx, bottom),
+        ZonePosition::Left => (left, cy),
+        ZonePosition::Right => (right, cy),
+    };
+    [x, y, x + bw, y + bh]
+}
@@ -0,0 +1,78 @@
+use crate::control;
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+// Один экземпляр на сессию Wayland: flock на файле рядом с управляющим
+// сокетом. Повторный запуск не создаёт второй оверлей, а пересылает свои
+// флаги (--toggle, --clear, --color ...) уже запущенному и выходит, см. cli.rs
+
+pub enum Instance {
+    // Блокировка наша; None - файл блокировки создать не удалось
+    First(Option<File>),
+    Running,
+}
+
+pub fn acquire() -> Instance {
+    let path = control::socket_path().with_extension("lock");
+    // В чужом каталоге блокировку не заводим: её мог бы держать кто угодно
+    if let Err(e) = control::create_runtime_dir() {
+        eprintln!("[instance] {}", e);
+        return Instance::First(None);
+    }
+    let file = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("[instance] failed to open {}: {}", path.display(), e);
+            return Instance::First(None);
+        }
+    };
+    // Блокировка снимается ядром вместе с процессом, даже при падении
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Instance::Running;
+    }
+    Instance::First(Some(file))
+}
+
+// Отправляет команды запущенному экземпляру; код выхода процесса
+pub fn forward(commands: &[String]) -> i32 {
+    if commands.is_empty() {
+        eprintln!("cherta is already running; pass a flag such as --toggle or use `cherta ctl`");
+        return 1;
+    }
+    let mut status = 0;
+    for command in commands {
+        match send_with_retry(command) {
+            Ok(reply) if reply.starts_with("ok") => {}
+            Ok(reply) => {
+                eprint!("cherta: {}: {}", command, reply);
+                status = 1;
+            }
+            Err(e) => {
+                eprintln!("cherta: {}", e);
+                return 1;
+            }
+        }
+    }
+    status
+}
+
+// Экземпляр мог взять блокировку, но ещё не открыть сокет
+fn send_with_retry(command: &str) -> Result<String, String> {
+    let mut attempts = 20;
+    loop {
+        match control::send(command) {
+            Err(_) if attempts > 0 => {
+                attempts -= 1;
+                thread::sleep(Duration::from_millis(100));
+            }
+            result => return result,
+        }
+    }
+}
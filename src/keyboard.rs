@@ -0,0 +1,138 @@
+use std::ffi::CString;
+use std::io::Read;
+use std::os::fd::OwnedFd;
+use std::os::raw::c_char;
+use xkbcommon_dl::{
+    xkb_context, xkb_context_flags, xkb_keymap, xkb_keymap_compile_flags, xkb_keymap_format,
+    xkb_keysym_flags, xkb_state, xkb_state_component, xkbcommon_option, XkbCommon,
+};
+use xkeysym::Keysym;
+
+// Обёртка над libxkbcommon (грузится через dlopen)
+pub struct Xkb {
+    lib: &'static XkbCommon,
+    context: *mut xkb_context,
+    keymap: *mut xkb_keymap,
+    state: *mut xkb_state,
+}
+
+impl Xkb {
+    pub fn from_fd(fd: OwnedFd, size: u32) -> Option<Self> {
+        let lib = xkbcommon_option()?;
+
+        let mut data = Vec::with_capacity(size as usize);
+        std::fs::File::from(fd).read_to_end(&mut data).ok()?;
+        if let Some(end) = data.iter().position(|&b| b == 0) {
+            data.truncate(end);
+        }
+        let keymap_str = CString::new(data).ok()?;
+
+        unsafe {
+            let context = (lib.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_FLAGS);
+            if context.is_null() {
+                return None;
+            }
+            let keymap = (lib.xkb_keymap_new_from_string)(
+                context,
+                keymap_str.as_ptr(),
+                xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+                xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            );
+            if keymap.is_null() {
+                (lib.xkb_context_unref)(context);
+                return None;
+            }
+            let state = (lib.xkb_state_new)(keymap);
+            if state.is_null() {
+                (lib.xkb_keymap_unref)(keymap);
+                (lib.xkb_context_unref)(context);
+                return None;
+            }
+            Some(Self {
+                lib,
+                context,
+                keymap,
+                state,
+            })
+        }
+    }
+
+    pub fn update_modifiers(&mut self, depressed: u32, latched: u32, locked: u32, group: u32) {
+        unsafe {
+            (self.lib.xkb_state_update_mask)(self.state, depressed, latched, locked, 0, 0, group);
+        }
+    }
+
+    // keycode из wl_keyboard (evdev), xkb ждёт смещение +8
+    pub fn key(&self, keycode: u32) -> (Keysym, Option<String>) {
+        let code = keycode + 8;
+        unsafe {
+            let sym = Keysym::new((self.lib.xkb_state_key_get_one_sym)(self.state, code));
+            let mut buf = [0 as c_char; 64];
+            let len =
+                (self.lib.xkb_state_key_get_utf8)(self.state, code, buf.as_mut_ptr(), buf.len());
+            let text = if len > 0 && (len as usize) < buf.len() {
+                let bytes = std::slice::from_raw_parts(buf.as_ptr() as *const u8, len as usize);
+                String::from_utf8(bytes.to_vec()).ok()
+            } else {
+                None
+            };
+            (sym, text)
+        }
+    }
+
+    // Символ клавиши без учёта модификаторов (первый уровень раскладки):
+    // для Ctrl+Shift+Z это "z", а не "Z"
+    pub fn base_key(&self, keycode: u32) -> Keysym {
+        let code = keycode + 8;
+        unsafe {
+            let layout = (self.lib.xkb_state_key_get_layout)(self.state, code);
+            let mut syms = std::ptr::null();
+            let n = (self.lib.xkb_keymap_key_get_syms_by_level)(
+                self.keymap,
+                code,
+                layout,
+                0,
+                &mut syms,
+            );
+            if n > 0 {
+                Keysym::new(*syms)
+            } else {
+                Keysym::NoSymbol
+            }
+        }
+    }
+
+    pub fn mod_active(&self, name: &str) -> bool {
+        let Ok(name) = CString::new(name) else {
+            return false;
+        };
+        unsafe {
+            (self.lib.xkb_state_mod_name_is_active)(
+                self.state,
+                name.as_ptr(),
+                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+            ) > 0
+        }
+    }
+}
+
+// "Escape", "z", "F1"... - имена как в xkbcommon, без учёта регистра
+pub fn keysym_from_name(name: &str) -> Option<Keysym> {
+    let lib = xkbcommon_option()?;
+    let name = CString::new(name).ok()?;
+    let sym = unsafe {
+        (lib.xkb_keysym_from_name)(name.as_ptr(), xkb_keysym_flags::XKB_KEYSYM_CASE_INSENSITIVE)
+    };
+    (sym != 0).then(|| Keysym::new(sym))
+}
+
+impl Drop for Xkb {
+    fn drop(&mut self) {
+        unsafe {
+            (self.lib.xkb_state_unref)(self.state);
+            (self.lib.xkb_keymap_unref)(self.keymap);
+            (self.lib.xkb_context_unref)(self.context);
+        }
+    }
+}
@@ -1,11 +1,38 @@
+mod annotation;
+mod bindings;
+mod cli;
+mod color;
 mod config;
+mod control;
+mod cursor;
+mod events;
+mod export;
+mod fsm;
+mod geometry;
+mod indicator;
+mod instance;
+mod keyboard;
+mod menu;
+mod migrate;
+mod reentry;
 mod renderer;
+#[cfg(test)]
+mod testutil;
+mod text;
+mod watcher;
 mod wayland;
+mod xdg;
 
-use config::Config;
+use annotation::{Annotation, StampKind};
+use color::Color;
+use config::{Config, SpotlightShape};
+use geometry::tessellate;
+use indicator::Indicator;
 use renderer::*;
 use std::os::raw::{c_int, c_void};
+use std::path::PathBuf;
 use std::time::Duration;
+use text::TextRenderer;
 use wayland::*;
 use wayland_client::Connection;
 
@@ -45,13 +72,52 @@ extern "C" {
 const EGL_OPENGL_ES_API: u32 = 0x30A0;
 
 fn main() {
-    let config = Config::load();
+    let command =
+        cli::parse(std::env::args().skip(1).collect()).unwrap_or_else(|e| cli::usage_error(&e));
+    match command {
+        cli::Command::Run(options) => run(options),
+        command => std::process::exit(cli::execute(command)),
+    }
+}
+
+fn run(options: cli::RunOptions) {
+    // Держим блокировку до выхода из run
+    let _lock = match instance::acquire() {
+        instance::Instance::First(lock) => lock,
+        instance::Instance::Running => std::process::exit(instance::forward(&options.forwarded())),
+    };
+
+    let mut config = options.source.load().unwrap_or_else(|e| {
+        eprintln!("cherta: {}", e);
+        std::process::exit(1);
+    });
+    if let Err(e) = options.apply(&mut config) {
+        eprintln!("cherta: {}", e);
+        std::process::exit(1);
+    }
 
     let conn = Connection::connect_to_env().unwrap();
     let mut event_queue = conn.new_event_queue();
-    let mut state = WaylandState::new();
+    let mut state = WaylandState::new(config);
+    if let Some(name) = &options.source.profile {
+        state.profile = name.clone();
+    }
+    state.config_file = options.source.config.clone();
 
     setup_wayland(&mut state, &conn, &mut event_queue);
+    reentry::install_toggle_signal();
+    let mut control = control::Server::bind();
+    let mut watcher = watcher::ConfigWatcher::new();
+    let mut watched_generation = None;
+    // Флаги первого запуска применяются к нему самому
+    for command in &options.commands {
+        if let Err(e) = command
+            .parse()
+            .and_then(|command| control::execute(&mut state, command))
+        {
+            eprintln!("cherta: {}: {}", command, e);
+        }
+    }
 
     let surface = state.surface.as_ref().unwrap();
     let wl_egl_window = create_egl_window(surface, state.width, state.height);
@@ -108,129 +174,286 @@ fn main() {
         let program = create_program(vertex_src, fragment_src).expect("Shader compilation failed");
         glUseProgram(program);
 
-        let proj_loc = glGetUniformLocation(program, b"proj\0".as_ptr());
-        let u_now_loc = glGetUniformLocation(program, b"u_now\0".as_ptr());
-        let u_ttl_loc = glGetUniformLocation(program, b"u_ttl\0".as_ptr());
-        let u_fade_start_loc = glGetUniformLocation(program, b"u_fade_start\0".as_ptr());
-        let u_color_loc = glGetUniformLocation(program, b"u_color\0".as_ptr());
-        let u_feather_loc = glGetUniformLocation(program, b"u_feather\0".as_ptr());
+        let proj_loc = glGetUniformLocation(program, c"proj".as_ptr());
+        let u_now_loc = glGetUniformLocation(program, c"u_now".as_ptr());
+        let u_ttl_loc = glGetUniformLocation(program, c"u_ttl".as_ptr());
+        let u_fade_start_loc = glGetUniformLocation(program, c"u_fade_start".as_ptr());
+        let u_color_loc = glGetUniformLocation(program, c"u_color".as_ptr());
+        let u_feather_loc = glGetUniformLocation(program, c"u_feather".as_ptr());
 
         let proj = ortho_matrix(state.width as f32, state.height as f32);
         glUniformMatrix4fv(proj_loc, 1, 0, proj.as_ptr());
 
-        let pos_loc = glGetAttribLocation(program, b"pos\0".as_ptr());
-        let t0_loc = glGetAttribLocation(program, b"t0\0".as_ptr());
+        let pos_loc = glGetAttribLocation(program, c"pos".as_ptr());
+        let t0_loc = glGetAttribLocation(program, c"t0".as_ptr());
+
+        let spotlight_program = create_program(
+            include_str!("../shaders/spotlight_vertex.glsl"),
+            include_str!("../shaders/spotlight_fragment.glsl"),
+        )
+        .expect("Spotlight shader compilation failed");
+        glUseProgram(spotlight_program);
+
+        let spot_proj_loc = glGetUniformLocation(spotlight_program, c"proj".as_ptr());
+        let spot_center_loc = glGetUniformLocation(spotlight_program, c"u_center".as_ptr());
+        let spot_radius_loc = glGetUniformLocation(spotlight_program, c"u_radius".as_ptr());
+        let spot_feather_loc = glGetUniformLocation(spotlight_program, c"u_feather".as_ptr());
+        let spot_dim_loc = glGetUniformLocation(spotlight_program, c"u_dim".as_ptr());
+        let spot_shape_loc = glGetUniformLocation(spotlight_program, c"u_shape".as_ptr());
+        let spot_aspect_loc = glGetUniformLocation(spotlight_program, c"u_aspect".as_ptr());
+        let spot_pos_loc = glGetAttribLocation(spotlight_program, c"pos".as_ptr());
+        glUniformMatrix4fv(spot_proj_loc, 1, 0, proj.as_ptr());
+
+        let (w, h) = (state.width as f32, state.height as f32);
+        let quad: [f32; 8] = [0.0, 0.0, w, 0.0, 0.0, h, w, h];
+        let mut quad_vbo = 0u32;
+        glGenBuffers(1, &mut quad_vbo);
+        glBindBuffer(GL_ARRAY_BUFFER, quad_vbo);
+        glBufferData(
+            GL_ARRAY_BUFFER,
+            std::mem::size_of_val(&quad) as isize,
+            quad.as_ptr() as *const c_void,
+            GL_STATIC_DRAW,
+        );
 
-        loop {
-            if matches!(state.input_state, wayland::InputState::Passthrough) {
-                let can_poll = if let Some(last_scroll) = state.last_scroll {
-                    last_scroll.elapsed().as_millis() > config.scroll_cooldown as u128
-                } else {
-                    true
-                };
+        let mut stream_vbo = 0u32;
+        glGenBuffers(1, &mut stream_vbo);
 
-                if can_poll
-                    && state.last_poll.elapsed().as_millis() > config.polling_interval as u128
-                {
-                    state.input_state = wayland::InputState::Capturing;
-                    state.set_input_passthrough(false);
-                    state.last_poll = std::time::Instant::now();
-                }
-            }
+        let mut text_renderer =
+            TextRenderer::new(&state.config, &proj).expect("Text shader compilation failed");
 
-            event_queue.dispatch_pending(&mut state).unwrap();
+        let mut text_generation = state.config_generation;
+        let mut indicator = Indicator::new();
 
-            glClearColor(0.0, 0.0, 0.0, 0.0);
-            glClear(GL_COLOR_BUFFER_BIT);
-
-            glLineWidth(config.thickness);
-            glUniform1f(u_ttl_loc, config.ttl);
-            glUniform1f(u_fade_start_loc, config.fade_start);
-            glUniform1f(u_feather_loc, config.line_feather);
-            glUniform4f(
-                u_color_loc,
-                config.color[0],
-                config.color[1],
-                config.color[2],
-                config.opacity,
-            );
+        glUseProgram(program);
 
-            let now = state.start_time.elapsed().as_secs_f32();
-            glUniform1f(u_now_loc, now);
+        while state.running {
+            state.tick_reentry();
 
-            state.strokes.retain(|stroke| {
-                if stroke.is_empty() {
-                    return false;
+            event_queue.dispatch_pending(&mut state).unwrap();
+            if let Some(control) = &mut control {
+                control.poll(&mut state);
+            }
+            if let Some(watcher) = &mut watcher {
+                if watcher.changed() {
+                    reload_config(&mut state, &options);
+                    watched_generation = None;
                 }
-                let age = now - stroke.last().unwrap().2;
-                age < config.ttl
-            });
-
-            for stroke in &state.strokes {
-                if stroke.len() < 2 {
-                    continue;
+                // Цепочка inherits могла поменяться вместе с файлом или профилем
+                if watched_generation != Some(state.config_generation) {
+                    watched_generation = Some(state.config_generation);
+                    watcher.watch(config_files(&state));
                 }
+            }
+            if text_generation != state.config_generation {
+                text_generation = state.config_generation;
+                text_renderer.reload(&state.config);
+            }
+            let now = state.start_time.elapsed().as_secs_f32();
+            state.expire_items(now);
+            let config = &state.config;
 
-                let points = if config.smooth_lines {
-                    smooth_points(stroke, config.min_point_distance)
-                } else {
-                    stroke.clone()
-                };
+            glClearColor(0.0, 0.0, 0.0, 0.0);
+            glClear(GL_COLOR_BUFFER_BIT);
 
-                if points.len() < 2 {
-                    continue;
-                }
+            // Скрытый оверлей - пустой кадр, аннотации ждут показа
+            if state.fsm.mode() == fsm::Mode::Hidden {
+                eglSwapBuffers(egl_display, egl_surface);
+                std::thread::sleep(Duration::from_millis(16));
+                continue;
+            }
 
-                let mut vbo = 0u32;
-                glGenBuffers(1, &mut vbo);
-                glBindBuffer(GL_ARRAY_BUFFER, vbo);
-                glBufferData(
-                    GL_ARRAY_BUFFER,
-                    (points.len() * 12) as isize,
-                    points.as_ptr() as *const c_void,
-                    GL_STATIC_DRAW,
+            // Вне выделенной области ничего не рисуем
+            if let Some([x0, y0, x1, y1]) = state.capture_rect {
+                glEnable(GL_SCISSOR_TEST);
+                glScissor(
+                    x0 as i32,
+                    state.height - y1 as i32,
+                    (x1 - x0) as i32,
+                    (y1 - y0) as i32,
                 );
+            }
 
-                glEnableVertexAttribArray(pos_loc as u32);
-                glVertexAttribPointer(pos_loc as u32, 2, GL_FLOAT, 0, 12, std::ptr::null());
+            if state.spotlight {
+                glUseProgram(spotlight_program);
+                glUniform2f(spot_center_loc, state.pointer_pos.0, state.pointer_pos.1);
+                glUniform1f(spot_radius_loc, state.spotlight_radius);
+                glUniform1f(spot_feather_loc, config.spotlight_feather);
+                glUniform1f(spot_dim_loc, config.spotlight_dim);
+                glUniform1i(
+                    spot_shape_loc,
+                    match state.spotlight_shape {
+                        SpotlightShape::Circle => 0,
+                        SpotlightShape::Rect => 1,
+                    },
+                );
+                glUniform1f(
+                    spot_aspect_loc,
+                    state.width as f32 / state.height.max(1) as f32,
+                );
 
-                glEnableVertexAttribArray(t0_loc as u32);
-                glVertexAttribPointer(t0_loc as u32, 1, GL_FLOAT, 0, 12, 8 as *const c_void);
+                glBindBuffer(GL_ARRAY_BUFFER, quad_vbo);
+                glEnableVertexAttribArray(spot_pos_loc as u32);
+                glVertexAttribPointer(spot_pos_loc as u32, 2, GL_FLOAT, 0, 8, std::ptr::null());
+                glDrawArrays(GL_TRIANGLE_STRIP, 0, 4);
+                glDisableVertexAttribArray(spot_pos_loc as u32);
 
-                glDrawArrays(GL_LINE_STRIP, 0, points.len() as c_int);
+                glUseProgram(program);
             }
 
-            if !state.current_stroke.is_empty() {
-                let points = if config.smooth_lines {
-                    smooth_points(&state.current_stroke, config.min_point_distance)
+            glUniform1f(u_ttl_loc, config.ttl);
+            glUniform1f(u_fade_start_loc, config.fade_start());
+            glUniform1f(u_feather_loc, config.renderer.feather);
+            glUniform1f(u_now_loc, now);
+
+            for item in &mut state.items {
+                match item {
+                    Annotation::Stroke(stroke) => {
+                        set_color(u_color_loc, stroke.color, config.pen.opacity);
+                        let points = if config.renderer.smooth_lines {
+                            smooth_points(&stroke.points, config.renderer.min_point_distance)
+                        } else {
+                            stroke.points.clone()
+                        };
+
+                        let strip = tessellate(&points, config.taper);
+                        draw_vertices(stream_vbo, GL_TRIANGLE_STRIP, &strip, pos_loc, t0_loc);
+                    }
+                    Annotation::Text(label) => {
+                        text_renderer.begin(now, config);
+                        label.size = text_renderer.draw_label(
+                            label,
+                            rgba(label.color, config.pen.opacity),
+                            config.text_background.map(Color::to_array),
+                            label.pinned,
+                        );
+                        glUseProgram(program);
+                    }
+                    Annotation::Stamp(stamp) => {
+                        let center = (stamp.x, stamp.y);
+                        let color = rgba(stamp.color, config.pen.opacity);
+                        if let StampKind::Number(_) = stamp.kind {
+                            set_color(u_color_loc, stamp.color, config.pen.opacity);
+                            let fan = circle_fan(center, config.stamp_size / 2.0, stamp.t);
+                            draw_vertices(stream_vbo, GL_TRIANGLE_FAN, &fan, pos_loc, t0_loc);
+                        }
+
+                        text_renderer.begin(now, config);
+                        match &stamp.kind {
+                            StampKind::Number(n) => {
+                                let white = [1.0, 1.0, 1.0, config.pen.opacity];
+                                text_renderer.draw_centered(&n.to_string(), center, stamp.t, white)
+                            }
+                            StampKind::Symbol(symbol) => {
+                                text_renderer.draw_centered(symbol, center, stamp.t, color)
+                            }
+                            StampKind::Image(name) => text_renderer.draw_image(
+                                name,
+                                center,
+                                config.stamp_size,
+                                stamp.t,
+                                config.pen.opacity,
+                            ),
+                        }
+                        glUseProgram(program);
+                    }
+                }
+            }
+
+            for stroke in state.active_strokes.values() {
+                set_color(u_color_loc, stroke.color, config.pen.opacity);
+                let points = if config.renderer.smooth_lines {
+                    smooth_points(&stroke.points, config.renderer.min_point_distance)
                 } else {
-                    state.current_stroke.clone()
+                    stroke.points.clone()
                 };
 
-                if points.len() >= 2 {
-                    let mut vbo = 0u32;
-                    glGenBuffers(1, &mut vbo);
-                    glBindBuffer(GL_ARRAY_BUFFER, vbo);
-                    glBufferData(
-                        GL_ARRAY_BUFFER,
-                        (points.len() * 12) as isize,
-                        points.as_ptr() as *const c_void,
-                        GL_STATIC_DRAW,
-                    );
+                let strip = tessellate(&points, config.taper);
+                draw_vertices(stream_vbo, GL_TRIANGLE_STRIP, &strip, pos_loc, t0_loc);
+            }
+
+            if let Some(draft) = &mut state.text_draft {
+                let color = rgba(draft.color, config.pen.opacity);
+                text_renderer.begin(now, config);
+                draft.size = text_renderer.draw_label(
+                    draft,
+                    color,
+                    config.text_background.map(Color::to_array),
+                    true,
+                );
+                if now.fract() < 0.5 {
+                    text_renderer.draw_caret(draft, color);
+                }
+                glUseProgram(program);
+            }
+
+            glDisable(GL_SCISSOR_TEST);
 
-                    glEnableVertexAttribArray(pos_loc as u32);
-                    glVertexAttribPointer(pos_loc as u32, 2, GL_FLOAT, 0, 12, std::ptr::null());
+            if let (Some(rect), Some(border)) = (state.capture_rect, config.region_border) {
+                text_renderer.begin(now, config);
+                text_renderer.draw_frame(rect, 1.0, border.to_array());
+                glUseProgram(program);
+            }
 
-                    glEnableVertexAttribArray(t0_loc as u32);
-                    glVertexAttribPointer(t0_loc as u32, 1, GL_FLOAT, 0, 12, 8 as *const c_void);
+            if let Some((sx, sy)) = state.selection_start {
+                let (x, y) = state.pointer_pos;
+                text_renderer.begin(now, config);
+                text_renderer.draw_frame(
+                    [sx.min(x), sy.min(y), sx.max(x), sy.max(y)],
+                    1.0,
+                    [1.0, 1.0, 1.0, 0.8],
+                );
+                glUseProgram(program);
+            }
 
-                    glDrawArrays(GL_LINE_STRIP, 0, points.len() as c_int);
+            if let Some(menu) = &state.menu {
+                text_renderer.begin(now, config);
+                for (tool, rect) in menu.items() {
+                    let bg = if tool == state.tool {
+                        [0.2, 0.45, 0.9, 0.9]
+                    } else {
+                        [0.1, 0.1, 0.1, 0.8]
+                    };
+                    text_renderer.draw_rect(rect, bg);
+                    let center = ((rect[0] + rect[2]) / 2.0, (rect[1] + rect[3]) / 2.0);
+                    text_renderer.draw_centered(tool.name(), center, now, [1.0; 4]);
                 }
+                glUseProgram(program);
             }
 
+            indicator.draw(&mut text_renderer, &state, now);
+            glUseProgram(program);
+
             eglSwapBuffers(egl_display, egl_surface);
 
             std::thread::sleep(Duration::from_millis(16));
         }
     }
 }
+
+// За какими файлами следить для перезагрузки
+fn config_files(state: &WaylandState) -> Vec<PathBuf> {
+    match &state.config_file {
+        Some(path) => vec![path.clone()],
+        None => Config::profile_files(&state.profile),
+    }
+}
+
+// Конфиг изменён на диске. Флаги командной строки остаются в силе, пока
+// профиль не сменили через сокет; при ошибке работает прежний конфиг
+fn reload_config(state: &mut WaylandState, options: &cli::RunOptions) {
+    let result = match &state.config_file {
+        Some(path) => Config::load_from(path),
+        None => Config::load_profile(&state.profile),
+    }
+    .and_then(|mut config| {
+        if state.launch_config {
+            options.apply(&mut config)?;
+        }
+        Ok(config)
+    });
+    match result {
+        Ok(config) => state.apply_config(config),
+        Err(e) => eprintln!("[config] reload failed, keeping the previous config: {}", e),
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config::Tool;
+
+// Меню выбора инструмента: ряд кнопок, открывается из горячей зоны
+
+const CELL_WIDTH: f32 = 96.0;
+const CELL_HEIGHT: f32 = 40.0;
+const GAP: f32 = 4.0;
+
+pub struct ToolMenu {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl ToolMenu {
+    // Меню у точки (x, y), сдвинутое так, чтобы целиком поместиться на экране
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        let n = Tool::ALL.len() as f32;
+        let menu_width = n * CELL_WIDTH + (n - 1.0) * GAP;
+        Self {
+            x: x.min(width - menu_width).max(0.0),
+            y: y.min(height - CELL_HEIGHT).max(0.0),
+        }
+    }
+
+    // Прямоугольники кнопок [x0, y0, x1, y1]
+    pub fn items(&self) -> impl Iterator<Item = (Tool, [f32; 4])> + '_ {
+        Tool::ALL.iter().enumerate().map(|(i, tool)| {
+            let x = self.x + i as f32 * (CELL_WIDTH + GAP);
+            (*tool, [x, self.y, x + CELL_WIDTH, self.y + CELL_HEIGHT])
+        })
+    }
+
+    pub fn hit(&self, x: f32, y: f32) -> Option<Tool> {
+        self.items()
+            .find(|(_, r)| x >= r[0] && x < r[2] && y >= r[1] && y < r[3])
+            .map(|(tool, _)| tool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_hits_cells() {
+        let menu = ToolMenu::new(0.0, 0.0, 1920.0, 1080.0);
+        assert_eq!(menu.hit(0.0, 0.0), Some(Tool::ALL[0]));
+        assert_eq!(
+            menu.hit(CELL_WIDTH - 0.1, CELL_HEIGHT - 0.1),
+            Some(Tool::ALL[0])
+        );
+        assert_eq!(menu.hit(CELL_WIDTH + GAP, 10.0), Some(Tool::ALL[1]));
+        let last = (Tool::ALL.len() - 1) as f32 * (CELL_WIDTH + GAP);
+        assert_eq!(
+            menu.hit(last + 1.0, 1.0),
+            Some(Tool::ALL[Tool::ALL.len() - 1])
+        );
+    }
+
+    #[test]
+    fn menu_misses_outside_and_gaps() {
+        let menu = ToolMenu::new(100.0, 100.0, 1920.0, 1080.0);
+        assert_eq!(menu.hit(99.9, 110.0), None);
+        assert_eq!(menu.hit(110.0, 99.9), None);
+        assert_eq!(menu.hit(110.0, 100.0 + CELL_HEIGHT), None);
+        // Щель между кнопками
+        assert_eq!(menu.hit(100.0 + CELL_WIDTH + GAP / 2.0, 110.0), None);
+        let (_, last) = menu.items().last().unwrap();
+        assert_eq!(menu.hit(last[2], 110.0), None);
+    }
+
+    #[test]
+    fn menu_stays_on_screen() {
+        let menu = ToolMenu::new(1900.0, 1070.0, 1920.0, 1080.0);
+        let (_, last) = menu.items().last().unwrap();
+        assert_eq!(last[2], 1920.0);
+        assert_eq!(last[3], 1080.0);
+        let menu = ToolMenu::new(-5.0, -5.0, 1920.0, 1080.0);
+        assert_eq!((menu.x, menu.y), (0.0, 0.0));
+    }
+}
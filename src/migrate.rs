@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Key, Table};
+
+// Обновление старых схем конфига до текущей. Файл без ключа version - первая
+// схема; каждый шаг переводит документ с версии N на N + 1. Новый шаг
+// добавляется в STEPS вместе с увеличением CURRENT. Правится сам документ,
+// поэтому комментарии и порядок ключей сохраняются
+
+pub const CURRENT: i64 = 2;
+
+type Step = fn(&mut Table) -> bool;
+
+const STEPS: [Step; (CURRENT - 1) as usize] = [v1_to_v2];
+
+// Some((исходная версия, новый текст)) - документ изменился и его стоит
+// записать обратно
+pub fn upgrade(data: &str) -> Result<Option<(i64, String)>, String> {
+    let mut doc: DocumentMut = data
+        .parse()
+        .map_err(|e: toml_edit::TomlError| e.to_string())?;
+    let version = match doc.get("version") {
+        None => 1,
+        Some(item) => match item.as_integer() {
+            Some(version) if (1..=CURRENT).contains(&version) => version,
+            Some(version) if version > CURRENT => {
+                return Err(format!(
+                    "config version {} is newer than this cherta supports ({})",
+                    version, CURRENT
+                ))
+            }
+            _ => return Err(format!("bad config version: {}", item.to_string().trim())),
+        },
+    };
+    let mut changed = false;
+    for step in &STEPS[(version - 1) as usize..] {
+        changed |= step(doc.as_table_mut());
+    }
+    if !changed {
+        return Ok(None);
+    }
+    // version - первой строкой, а не в конце ключей верхнего уровня
+    let data = if doc.contains_key("version") {
+        doc["version"] = toml_edit::value(CURRENT);
+        doc.to_string()
+    } else {
+        format!("version = {}\n{}", CURRENT, doc)
+    };
+    Ok(Some((version, data)))
+}
+
+// Перо и отрисовка в своих разделах:
+// color, opacity, thickness -> [pen]; smooth_lines, min_point_distance,
+// line_feather -> [renderer] (feather)
+fn v1_to_v2(table: &mut Table) -> bool {
+    let mut changed = false;
+    for key in ["thickness", "color", "opacity"] {
+        changed |= move_key(table, key, "pen", key);
+    }
+    for key in ["smooth_lines", "min_point_distance"] {
+        changed |= move_key(table, key, "renderer", key);
+    }
+    changed |= move_key(table, "line_feather", "renderer", "feather");
+    changed
+}
+
+// Значение, уже заданное в новом разделе, важнее старого ключа. Комментарий
+// над ключом переезжает вместе с ним
+fn move_key(table: &mut Table, from: &str, section: &str, to: &str) -> bool {
+    let Some((key, value)) = table.remove_entry(from) else {
+        return false;
+    };
+    let section = table
+        .entry(section)
+        .or_insert_with(|| Item::Table(Table::new()));
+    match section {
+        Item::Table(section) if !section.contains_key(to) => {
+            let key = Key::new(to).with_leaf_decor(key.leaf_decor().clone());
+            section.insert_formatted(&key, value);
+        }
+        // pen = { ... } в одну строку
+        Item::Value(toml_edit::Value::InlineTable(section)) if !section.contains_key(to) => {
+            if let Ok(value) = value.into_value() {
+                section.insert(to, value);
+            }
+        }
+        _ => {}
+    }
+    true
+}
+
+// Обновлённый файл пишется только рядом с копией старого (<file>.v<N>.bak)
+pub fn save_upgraded(path: &Path, old: &str, version: i64, data: &str) -> Result<(), String> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", version));
+    let backup = Path::new(&backup);
+    if backup.exists() {
+        return Err(format!("{} already exists", backup.display()));
+    }
+    fs::write(backup, old).map_err(|e| format!("{}: {}", backup.display(), e))?;
+    fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    eprintln!(
+        "[config] upgraded {} to version {}, the old file is saved as {}",
+        path.display(),
+        CURRENT,
+        backup.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+
+    const V1: &str = "\
+# мой конфиг
+ttl = 5.0
+# толщина для доски
+thickness = 4.0
+color = \"red\"
+opacity = 0.8
+smooth_lines = false
+line_feather = 1.5
+";
+
+    fn upgraded(data: &str) -> (i64, String, toml::Table) {
+        let (version, text) = upgrade(data).unwrap().expect("not upgraded");
+        let table = text.parse().unwrap();
+        (version, text, table)
+    }
+
+    #[test]
+    fn v1_keys_move_to_sections() {
+        let (version, text, table) = upgraded(V1);
+        assert_eq!(version, 1);
+        assert!(text.starts_with("version = 2\n"));
+        assert_eq!(table["version"].as_integer(), Some(2));
+        assert_eq!(table["ttl"].as_float(), Some(5.0));
+        let pen = table["pen"].as_table().unwrap();
+        assert_eq!(pen["thickness"].as_float(), Some(4.0));
+        assert_eq!(pen["color"].as_str(), Some("red"));
+        assert_eq!(pen["opacity"].as_float(), Some(0.8));
+        let renderer = table["renderer"].as_table().unwrap();
+        assert_eq!(renderer["smooth_lines"].as_bool(), Some(false));
+        assert_eq!(renderer["feather"].as_float(), Some(1.5));
+        for key in [
+            "thickness",
+            "color",
+            "opacity",
+            "smooth_lines",
+            "line_feather",
+        ] {
+            assert!(!table.contains_key(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn comments_survive() {
+        let (_, text, _) = upgraded(V1);
+        assert!(text.contains("# мой конфиг"));
+        assert!(text.contains("# толщина для доски\nthickness = 4.0"));
+    }
+
+    #[test]
+    fn section_value_wins_over_old_key() {
+        let (_, _, table) = upgraded("thickness = 4.0\n[pen]\nthickness = 9.0\n");
+        assert_eq!(table["pen"]["thickness"].as_float(), Some(9.0));
+        let (_, _, table) = upgraded("color = \"red\"\npen = { color = \"blue\" }\n");
+        assert_eq!(table["pen"]["color"].as_str(), Some("blue"));
+    }
+
+    #[test]
+    fn current_and_bad_versions() {
+        assert_eq!(upgrade("version = 2\nttl = 1.0\n"), Ok(None));
+        assert_eq!(upgrade("ttl = 1.0\n"), Ok(None));
+        assert!(upgrade("version = 3\n").unwrap_err().contains("newer"));
+        assert!(upgrade("version = \"two\"\n").is_err());
+        assert!(upgrade("version = 0\n").is_err());
+    }
+
+    #[test]
+    fn save_writes_backup_once() {
+        let dir = temp_dir("migrate");
+        let path = dir.join("default.toml");
+        fs::write(&path, V1).unwrap();
+        let (version, text, _) = upgraded(V1);
+
+        save_upgraded(&path, V1, version, &text).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("default.toml.v1.bak")).unwrap(),
+            V1
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+        // Вторая попытка не затирает первую копию и сам файл
+        let error = save_upgraded(&path, "ttl = 9.0\n", version, "version = 2\n").unwrap_err();
+        assert!(error.contains("already exists"));
+        assert_eq!(
+            fs::read_to_string(dir.join("default.toml.v1.bak")).unwrap(),
+            V1
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::config::{Config, Reentry};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// Как оверлей возвращается из PASSTHROUGH в режим захвата (и когда сам
+// отпускает ввод). В PASSTHROUGH поверхность почти не получает событий,
+// поэтому стратегия видит только то, что ей передаёт WaylandState
+
+pub enum Signal {
+    // Ввод только что отпущен (колесо, Escape, toggle-passthrough)
+    PassthroughEntered,
+    StylusProximity(bool),
+    // Известно ли, где перо было в последний раз: пробовать есть где
+    StylusSeen(bool),
+    // Мышь над оверлеем в PASSTHROUGH: ею сейчас работают
+    PointerActivity,
+}
+
+pub trait ReentryStrategy {
+    // Каждый кадр; Some(true) - захватить ввод, Some(false) - отпустить
+    fn tick(&mut self, _capturing: bool) -> Option<bool> {
+        None
+    }
+
+    fn signal(&mut self, _signal: Signal) -> Option<bool> {
+        None
+    }
+
+    // Input region ненадолго расширен, только чтобы проверить ввод; режим
+    // остаётся PASSTHROUGH
+    fn probing(&self) -> bool {
+        false
+    }
+}
+
+pub fn from_config(config: &Config) -> Box<dyn ReentryStrategy> {
+    match config.reentry {
+        Reentry::Timed => Box::new(Timed::new(config)),
+        Reentry::Toggle => Box::new(Toggle),
+        Reentry::Stylus => Box::new(Stylus::new(config)),
+    }
+}
+
+// Как раньше: после scroll_cooldown мс тишины - захват каждые polling_interval мс
+pub struct Timed {
+    cooldown: Duration,
+    interval: Duration,
+    entered: Instant,
+    last_poll: Instant,
+}
+
+impl Timed {
+    fn new(config: &Config) -> Self {
+        Self {
+            cooldown: Duration::from_millis(config.scroll_cooldown),
+            interval: Duration::from_millis(config.polling_interval),
+            entered: Instant::now(),
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+impl ReentryStrategy for Timed {
+    fn tick(&mut self, capturing: bool) -> Option<bool> {
+        if capturing
+            || self.entered.elapsed() <= self.cooldown
+            || self.last_poll.elapsed() <= self.interval
+        {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        Some(true)
+    }
+
+    fn signal(&mut self, signal: Signal) -> Option<bool> {
+        if let Signal::PassthroughEntered = signal {
+            self.entered = Instant::now();
+        }
+        None
+    }
+}
+
+// Только явное переключение: привязка, горячая зона или SIGUSR1
+pub struct Toggle;
+
+impl ReentryStrategy for Toggle {}
+
+// Захват, пока перо рядом с планшетом. Приближение пера композитор сообщает
+// только поверхности под ним, поэтому в PASSTHROUGH оверлей периодически
+// ненадолго расширяет input region вокруг места, где перо видели в последний
+// раз (PROBE_RADIUS), и смотрит, не пришло ли proximity_in. Режим автомата
+// при этом не меняется, а мышь и касания в этом квадрате во время пробы
+// отбрасываются. Пока перо ни разу не видели, проб нет; если над оверлеем
+// появилась мышь, они откладываются на interval
+pub struct Stylus {
+    interval: Duration,
+    probe: Option<Instant>,
+    last_probe: Instant,
+    seen: bool,
+}
+
+// Проба занимает не больше 1/20 времени, даже при маленьком polling_interval
+const PROBE_TIME: Duration = Duration::from_millis(20);
+const PROBE_DUTY: u32 = 20;
+// Половина стороны квадрата пробы, px
+pub const PROBE_RADIUS: f32 = 300.0;
+
+impl Stylus {
+    fn new(config: &Config) -> Self {
+        Self {
+            interval: Duration::from_millis(config.polling_interval).max(PROBE_TIME * PROBE_DUTY),
+            probe: None,
+            last_probe: Instant::now(),
+            seen: false,
+        }
+    }
+}
+
+impl ReentryStrategy for Stylus {
+    fn tick(&mut self, capturing: bool) -> Option<bool> {
+        if capturing || !self.seen {
+            self.probe = None;
+            return None;
+        }
+        match self.probe {
+            Some(start) if start.elapsed() > PROBE_TIME => self.probe = None,
+            Some(_) => {}
+            None if self.last_probe.elapsed() > self.interval => {
+                self.last_probe = Instant::now();
+                self.probe = Some(Instant::now());
+            }
+            None => {}
+        }
+        None
+    }
+
+    fn signal(&mut self, signal: Signal) -> Option<bool> {
+        match signal {
+            Signal::StylusProximity(near) => {
+                self.probe = None;
+                Some(near)
+            }
+            Signal::StylusSeen(seen) => {
+                self.seen = seen;
+                None
+            }
+            Signal::PassthroughEntered | Signal::PointerActivity => {
+                self.probe = None;
+                self.last_probe = Instant::now();
+                None
+            }
+        }
+    }
+
+    fn probing(&self) -> bool {
+        self.probe.is_some()
+    }
+}
+
+// SIGUSR1 переключает режим, SIGUSR2 скрывает/показывает оверлей - удобно
+// повесить на горячие клавиши композитора
+static TOGGLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+static VISIBILITY_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    if signal == libc::SIGUSR1 {
+        TOGGLE_REQUESTED.store(true, Ordering::Relaxed);
+    } else {
+        VISIBILITY_REQUESTED.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn install_toggle_signal() {
+    unsafe {
+        libc::signal(libc::SIGUSR1, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGUSR2, on_signal as *const () as libc::sighandler_t);
+    }
+}
+
+pub fn take_toggle_request() -> bool {
+    TOGGLE_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+pub fn take_visibility_request() -> bool {
+    VISIBILITY_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Момент в прошлом: тесты не ждут таймеров, а сдвигают отметки времени
+    fn ago(ms: u64) -> Instant {
+        Instant::now() - Duration::from_millis(ms)
+    }
+
+    fn timed() -> Timed {
+        Timed {
+            cooldown: Duration::from_millis(500),
+            interval: Duration::from_millis(50),
+            entered: ago(1000),
+            last_poll: ago(1000),
+        }
+    }
+
+    fn stylus() -> Stylus {
+        Stylus {
+            interval: Duration::from_millis(400),
+            probe: None,
+            last_probe: Instant::now(),
+            seen: true,
+        }
+    }
+
+    #[test]
+    fn timed_waits_for_cooldown() {
+        let mut timed = timed();
+        assert_eq!(timed.signal(Signal::PassthroughEntered), None);
+        assert_eq!(timed.tick(false), None);
+        timed.entered = ago(600);
+        assert_eq!(timed.tick(false), Some(true));
+    }
+
+    #[test]
+    fn timed_polls_at_interval() {
+        let mut timed = timed();
+        assert_eq!(timed.tick(false), Some(true));
+        // Следующая попытка - не раньше чем через polling_interval
+        assert_eq!(timed.tick(false), None);
+        timed.last_poll = ago(60);
+        assert_eq!(timed.tick(false), Some(true));
+    }
+
+    #[test]
+    fn timed_is_idle_while_capturing() {
+        let mut timed = timed();
+        assert_eq!(timed.tick(true), None);
+        assert!(!timed.probing());
+    }
+
+    #[test]
+    fn stylus_probes_and_gives_up() {
+        let mut stylus = stylus();
+        assert_eq!(stylus.tick(false), None);
+        assert!(!stylus.probing());
+        stylus.last_probe = ago(410);
+        // Проба не захватывает ввод, только расширяет input region
+        assert_eq!(stylus.tick(false), None);
+        assert!(stylus.probing());
+        assert_eq!(stylus.tick(false), None);
+        assert!(stylus.probing());
+        // Перо не появилось за PROBE_TIME - проба заканчивается
+        stylus.probe = Some(ago(30));
+        assert_eq!(stylus.tick(false), None);
+        assert!(!stylus.probing());
+        // Следующая - только через interval
+        assert_eq!(stylus.tick(false), None);
+        assert!(!stylus.probing());
+    }
+
+    #[test]
+    fn stylus_probe_is_a_small_part_of_the_interval() {
+        let config = Config {
+            polling_interval: 10,
+            ..Config::default()
+        };
+        let stylus = Stylus::new(&config);
+        assert!(stylus.interval >= PROBE_TIME * PROBE_DUTY);
+    }
+
+    #[test]
+    fn stylus_follows_proximity() {
+        let mut stylus = stylus();
+        stylus.probe = Some(ago(10));
+        assert_eq!(stylus.signal(Signal::StylusProximity(true)), Some(true));
+        assert!(!stylus.probing());
+        // Пока перо рядом, захват держится
+        assert_eq!(stylus.tick(true), None);
+        assert_eq!(stylus.signal(Signal::StylusProximity(false)), Some(false));
+        assert_eq!(stylus.tick(false), None);
+    }
+
+    #[test]
+    fn stylus_does_not_probe_while_capturing() {
+        let mut stylus = stylus();
+        stylus.probe = Some(ago(10));
+        assert_eq!(stylus.tick(true), None);
+        assert!(!stylus.probing());
+        stylus.last_probe = ago(410);
+        assert_eq!(stylus.tick(true), None);
+        assert!(!stylus.probing());
+    }
+
+    #[test]
+    fn stylus_waits_after_release() {
+        let mut stylus = stylus();
+        stylus.last_probe = ago(410);
+        assert_eq!(stylus.signal(Signal::PassthroughEntered), None);
+        assert_eq!(stylus.tick(false), None);
+        assert!(!stylus.probing());
+    }
+
+    #[test]
+    fn stylus_needs_a_known_position() {
+        let mut stylus = stylus();
+        stylus.signal(Signal::StylusSeen(false));
+        stylus.last_probe = ago(410);
+        assert_eq!(stylus.tick(false), None);
+        assert!(!stylus.probing());
+        stylus.signal(Signal::StylusSeen(true));
+        assert_eq!(stylus.tick(false), None);
+        assert!(stylus.probing());
+    }
+
+    #[test]
+    fn stylus_backs_off_from_the_mouse() {
+        let mut stylus = stylus();
+        stylus.probe = Some(ago(10));
+        assert_eq!(stylus.signal(Signal::PointerActivity), None);
+        assert!(!stylus.probing());
+        assert_eq!(stylus.tick(false), None);
+        assert!(!stylus.probing());
+    }
+
+    #[test]
+    fn toggle_never_acts_by_itself() {
+        let mut toggle = Toggle;
+        assert_eq!(toggle.tick(false), None);
+        assert_eq!(toggle.signal(Signal::StylusProximity(true)), None);
+        assert_eq!(toggle.signal(Signal::PassthroughEntered), None);
+    }
+}
@@ -1,4 +1,6 @@
-use std::os::raw::{c_int, c_void};
+use crate::annotation::Point;
+use crate::color::Color;
+use std::os::raw::{c_char, c_int, c_void};
 
 #[link(name = "GLESv2")]
 extern "C" {
@@ -6,8 +8,9 @@ extern "C" {
     pub fn glClear(mask: u32);
     pub fn glViewport(x: c_int, y: c_int, w: c_int, h: c_int);
     pub fn glEnable(cap: u32);
+    pub fn glDisable(cap: u32);
+    pub fn glScissor(x: c_int, y: c_int, w: c_int, h: c_int);
     pub fn glBlendFunc(sfactor: u32, dfactor: u32);
-    pub fn glLineWidth(width: f32);
     pub fn glHint(target: u32, mode: u32);
     pub fn glCreateShader(shader_type: u32) -> u32;
     pub fn glShaderSource(
@@ -24,15 +27,18 @@ extern "C" {
     pub fn glLinkProgram(program: u32);
     pub fn glGetProgramiv(program: u32, pname: u32, params: *mut c_int);
     pub fn glUseProgram(program: u32);
-    pub fn glGetUniformLocation(program: u32, name: *const u8) -> c_int;
+    pub fn glGetUniformLocation(program: u32, name: *const c_char) -> c_int;
     pub fn glUniform1f(location: c_int, v0: f32);
+    pub fn glUniform1i(location: c_int, v0: c_int);
+    pub fn glUniform2f(location: c_int, v0: f32, v1: f32);
     pub fn glUniform4f(location: c_int, v0: f32, v1: f32, v2: f32, v3: f32);
     pub fn glUniformMatrix4fv(location: c_int, count: c_int, transpose: u8, value: *const f32);
     pub fn glGenBuffers(n: c_int, buffers: *mut u32);
     pub fn glBindBuffer(target: u32, buffer: u32);
     pub fn glBufferData(target: u32, size: isize, data: *const c_void, usage: u32);
-    pub fn glGetAttribLocation(program: u32, name: *const u8) -> c_int;
+    pub fn glGetAttribLocation(program: u32, name: *const c_char) -> c_int;
     pub fn glEnableVertexAttribArray(index: u32);
+    pub fn glDisableVertexAttribArray(index: u32);
     pub fn glVertexAttribPointer(
         index: u32,
         size: c_int,
@@ -42,10 +48,38 @@ extern "C" {
         pointer: *const c_void,
     );
     pub fn glDrawArrays(mode: u32, first: c_int, count: c_int);
+    pub fn glGenTextures(n: c_int, textures: *mut u32);
+    pub fn glDeleteTextures(n: c_int, textures: *const u32);
+    pub fn glBindTexture(target: u32, texture: u32);
+    pub fn glTexParameteri(target: u32, pname: u32, param: c_int);
+    pub fn glPixelStorei(pname: u32, param: c_int);
+    pub fn glTexImage2D(
+        target: u32,
+        level: c_int,
+        internalformat: c_int,
+        width: c_int,
+        height: c_int,
+        border: c_int,
+        format: u32,
+        type_: u32,
+        pixels: *const c_void,
+    );
+    pub fn glTexSubImage2D(
+        target: u32,
+        level: c_int,
+        xoffset: c_int,
+        yoffset: c_int,
+        width: c_int,
+        height: c_int,
+        format: u32,
+        type_: u32,
+        pixels: *const c_void,
+    );
 }
 
 pub const GL_COLOR_BUFFER_BIT: u32 = 0x4000;
 pub const GL_BLEND: u32 = 0x0BE2;
+pub const GL_SCISSOR_TEST: u32 = 0x0C11;
 pub const GL_ONE: u32 = 1;
 pub const GL_ONE_MINUS_SRC_ALPHA: u32 = 0x0303;
 pub const GL_LINE_SMOOTH: u32 = 0x0B20;
@@ -55,10 +89,24 @@ pub const GL_VERTEX_SHADER: u32 = 0x8B31;
 pub const GL_FRAGMENT_SHADER: u32 = 0x8B30;
 pub const GL_ARRAY_BUFFER: u32 = 0x8892;
 pub const GL_STATIC_DRAW: u32 = 0x88E4;
+pub const GL_STREAM_DRAW: u32 = 0x88E0;
 pub const GL_FLOAT: u32 = 0x1406;
-pub const GL_LINE_STRIP: u32 = 0x0003;
+pub const GL_TRIANGLES: u32 = 0x0004;
+pub const GL_TRIANGLE_STRIP: u32 = 0x0005;
+pub const GL_TRIANGLE_FAN: u32 = 0x0006;
 pub const GL_COMPILE_STATUS: u32 = 0x8B81;
 pub const GL_LINK_STATUS: u32 = 0x8B82;
+pub const GL_TEXTURE_2D: u32 = 0x0DE1;
+pub const GL_ALPHA: u32 = 0x1906;
+pub const GL_RGBA: u32 = 0x1908;
+pub const GL_UNSIGNED_BYTE: u32 = 0x1401;
+pub const GL_TEXTURE_MIN_FILTER: u32 = 0x2801;
+pub const GL_TEXTURE_MAG_FILTER: u32 = 0x2800;
+pub const GL_TEXTURE_WRAP_S: u32 = 0x2802;
+pub const GL_TEXTURE_WRAP_T: u32 = 0x2803;
+pub const GL_LINEAR: u32 = 0x2601;
+pub const GL_CLAMP_TO_EDGE: u32 = 0x812F;
+pub const GL_UNPACK_ALIGNMENT: u32 = 0x0CF5;
 
 pub fn compile_shader(src: &str, shader_type: u32) -> Result<u32, String> {
     unsafe {
@@ -99,6 +147,60 @@ pub fn create_program(vs_src: &str, fs_src: &str) -> Result<u32, String> {
     }
 }
 
+// Загружает вершины (x, y, t0) в общий потоковый буфер и рисует их
+pub fn draw_vertices(
+    vbo: u32,
+    mode: u32,
+    vertices: &[(f32, f32, f32)],
+    pos_loc: c_int,
+    t0_loc: c_int,
+) {
+    unsafe {
+        glBindBuffer(GL_ARRAY_BUFFER, vbo);
+        glBufferData(
+            GL_ARRAY_BUFFER,
+            std::mem::size_of_val(vertices) as isize,
+            vertices.as_ptr() as *const c_void,
+            GL_STREAM_DRAW,
+        );
+
+        glEnableVertexAttribArray(pos_loc as u32);
+        glVertexAttribPointer(pos_loc as u32, 2, GL_FLOAT, 0, 12, std::ptr::null());
+
+        glEnableVertexAttribArray(t0_loc as u32);
+        glVertexAttribPointer(t0_loc as u32, 1, GL_FLOAT, 0, 12, 8 as *const c_void);
+
+        glDrawArrays(mode, 0, vertices.len() as c_int);
+
+        glDisableVertexAttribArray(pos_loc as u32);
+        glDisableVertexAttribArray(t0_loc as u32);
+    }
+}
+
+// Шейдер штрихов не домножает цвет на альфу, а смешивание - для
+// premultiplied, поэтому домножаем здесь
+pub fn set_color(loc: c_int, color: Color, opacity: f32) {
+    let [r, g, b, a] = rgba(color, opacity);
+    unsafe {
+        glUniform4f(loc, r * a, g * a, b * a, a);
+    }
+}
+
+pub fn rgba(color: Color, opacity: f32) -> [f32; 4] {
+    let [r, g, b] = color.rgb;
+    [r, g, b, color.alpha * opacity]
+}
+
+pub fn circle_fan(center: (f32, f32), radius: f32, t: f32) -> Vec<(f32, f32, f32)> {
+    let segments = 32;
+    let mut fan = vec![(center.0, center.1, t)];
+    for i in 0..=segments {
+        let a = i as f32 / segments as f32 * std::f32::consts::TAU;
+        fan.push((center.0 + radius * a.cos(), center.1 + radius * a.sin(), t));
+    }
+    fan
+}
+
 pub fn ortho_matrix(width: f32, height: f32) -> [f32; 16] {
     let mut m = [0.0f32; 16];
     m[0] = 2.0 / width;
@@ -111,7 +213,7 @@ pub fn ortho_matrix(width: f32, height: f32) -> [f32; 16] {
 }
 
 // Простое сглаживание через децимацию близких точек
-pub fn smooth_points(points: &[(f32, f32, f32)], min_distance: f32) -> Vec<(f32, f32, f32)> {
+pub fn smooth_points(points: &[Point], min_distance: f32) -> Vec<Point> {
     if points.len() < 2 {
         return points.to_vec();
     }
@@ -119,7 +221,7 @@ pub fn smooth_points(points: &[(f32, f32, f32)], min_distance: f32) -> Vec<(f32,
     let mut result = vec![points[0]];
     for point in points.iter().skip(1) {
         let last = result.last().unwrap();
-        let dist = ((point.0 - last.0).powi(2) + (point.1 - last.1).powi(2)).sqrt();
+        let dist = ((point.x - last.x).powi(2) + (point.y - last.y).powi(2)).sqrt();
         if dist >= min_distance {
             result.push(*point);
         }
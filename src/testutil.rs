@@ -0,0 +1,17 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Общие помощники тестов
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+// Пустой временный каталог, свой на каждый вызов: тесты идут параллельно
+pub fn temp_dir(name: &str) -> PathBuf {
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let dir =
+        std::env::temp_dir().join(format!("cherta-test-{}-{}-{}", std::process::id(), n, name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,543 @@
+use crate::color::Color;
+use crate::config::Config;
+use crate::renderer::*;
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::path::Path;
+
+const ATLAS_SIZE: u32 = 1024;
+const BOX_PADDING: f32 = 6.0;
+
+pub struct TextLabel {
+    pub x: f32,
+    pub y: f32,
+    pub text: String,
+    pub color: Color,
+    pub t: f32,
+    pub pinned: bool,
+    // Размер текста по метрикам атласа с последней отрисовки - для ластика
+    pub size: (f32, f32),
+}
+
+impl TextLabel {
+    pub fn new(x: f32, y: f32, color: Color, t: f32) -> Self {
+        Self {
+            x,
+            y,
+            text: String::new(),
+            color,
+            t,
+            pinned: false,
+            size: (0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GlyphInfo {
+    uv: [f32; 4],
+    offset: (f32, f32),
+    size: (f32, f32),
+    advance: f32,
+}
+
+// Атлас глифов в одной GL_ALPHA текстуре, глифы растеризуются по мере надобности
+pub struct GlyphAtlas {
+    font: Option<FontArc>,
+    scale: PxScale,
+    texture: u32,
+    glyphs: HashMap<char, GlyphInfo>,
+    cursor: (u32, u32),
+    row_height: u32,
+}
+
+impl GlyphAtlas {
+    pub fn new(font_path: &str, size: f32) -> Self {
+        let font = match std::fs::read(font_path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| FontArc::try_from_vec(data).map_err(|e| e.to_string()))
+        {
+            Ok(font) => Some(font),
+            Err(e) => {
+                eprintln!("[text] failed to load font {}: {}", font_path, e);
+                None
+            }
+        };
+
+        let mut texture = 0u32;
+        unsafe {
+            glGenTextures(1, &mut texture);
+            glBindTexture(GL_TEXTURE_2D, texture);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as c_int);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as c_int);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as c_int);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as c_int);
+            glPixelStorei(GL_UNPACK_ALIGNMENT, 1);
+
+            let mut pixels = vec![0u8; (ATLAS_SIZE * ATLAS_SIZE) as usize];
+            // Белый квадрат в углу - для сплошных прямоугольников (фон, каретка)
+            for y in 0..4 {
+                for x in 0..4 {
+                    pixels[(y * ATLAS_SIZE + x) as usize] = 255;
+                }
+            }
+            glTexImage2D(
+                GL_TEXTURE_2D,
+                0,
+                GL_ALPHA as c_int,
+                ATLAS_SIZE as c_int,
+                ATLAS_SIZE as c_int,
+                0,
+                GL_ALPHA,
+                GL_UNSIGNED_BYTE,
+                pixels.as_ptr() as *const c_void,
+            );
+        }
+
+        Self {
+            font,
+            scale: PxScale::from(size),
+            texture,
+            glyphs: HashMap::new(),
+            cursor: (8, 0),
+            row_height: 8,
+        }
+    }
+
+    fn white_uv(&self) -> [f32; 4] {
+        let c = 2.0 / ATLAS_SIZE as f32;
+        [c, c, c, c]
+    }
+
+    pub fn ascent(&self) -> f32 {
+        self.font
+            .as_ref()
+            .map(|f| f.as_scaled(self.scale).ascent())
+            .unwrap_or(0.0)
+    }
+
+    pub fn line_height(&self) -> f32 {
+        self.font
+            .as_ref()
+            .map(|f| {
+                let f = f.as_scaled(self.scale);
+                f.ascent() - f.descent() + f.line_gap()
+            })
+            .unwrap_or(0.0)
+    }
+
+    fn glyph(&mut self, c: char) -> Option<GlyphInfo> {
+        if let Some(info) = self.glyphs.get(&c) {
+            return Some(*info);
+        }
+        let font = self.font.as_ref()?;
+        let scaled = font.as_scaled(self.scale);
+        let id = font.glyph_id(c);
+        let advance = scaled.h_advance(id);
+
+        let mut info = GlyphInfo {
+            uv: [0.0; 4],
+            offset: (0.0, 0.0),
+            size: (0.0, 0.0),
+            advance,
+        };
+
+        if let Some(outlined) =
+            font.outline_glyph(id.with_scale_and_position(self.scale, point(0.0, 0.0)))
+        {
+            let bounds = outlined.px_bounds();
+            let w = bounds.width() as u32;
+            let h = bounds.height() as u32;
+
+            if self.cursor.0 + w + 1 > ATLAS_SIZE {
+                self.cursor = (0, self.cursor.1 + self.row_height + 1);
+                self.row_height = 0;
+            }
+            if self.cursor.1 + h + 1 > ATLAS_SIZE {
+                eprintln!("[text] glyph atlas is full");
+                return None;
+            }
+
+            let mut pixels = vec![0u8; (w * h) as usize];
+            outlined.draw(|x, y, coverage| {
+                if x < w && y < h {
+                    pixels[(y * w + x) as usize] = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+                }
+            });
+
+            let (ax, ay) = self.cursor;
+            unsafe {
+                glBindTexture(GL_TEXTURE_2D, self.texture);
+                glTexSubImage2D(
+                    GL_TEXTURE_2D,
+                    0,
+                    ax as c_int,
+                    ay as c_int,
+                    w as c_int,
+                    h as c_int,
+                    GL_ALPHA,
+                    GL_UNSIGNED_BYTE,
+                    pixels.as_ptr() as *const c_void,
+                );
+            }
+
+            let s = ATLAS_SIZE as f32;
+            info.uv = [
+                ax as f32 / s,
+                ay as f32 / s,
+                (ax + w) as f32 / s,
+                (ay + h) as f32 / s,
+            ];
+            info.offset = (bounds.min.x, bounds.min.y);
+            info.size = (w as f32, h as f32);
+
+            self.cursor.0 += w + 1;
+            self.row_height = self.row_height.max(h);
+        }
+
+        self.glyphs.insert(c, info);
+        Some(info)
+    }
+
+    fn layout(&mut self, text: &str, x: f32, y: f32, t: f32) -> Layout {
+        let line_height = self.line_height();
+        let mut baseline = y + self.ascent();
+        let mut pen_x = x;
+        let mut width: f32 = 0.0;
+        let mut vertices = Vec::new();
+
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x;
+                baseline += line_height;
+                continue;
+            }
+            let Some(info) = self.glyph(c) else {
+                continue;
+            };
+            if info.size.0 > 0.0 {
+                let x0 = pen_x + info.offset.0;
+                let y0 = baseline + info.offset.1;
+                push_quad(
+                    &mut vertices,
+                    [x0, y0, x0 + info.size.0, y0 + info.size.1],
+                    info.uv,
+                    t,
+                );
+            }
+            pen_x += info.advance;
+            width = width.max(pen_x - x);
+        }
+
+        let lines = text.split('\n').count() as f32;
+        Layout {
+            vertices,
+            width,
+            height: lines * line_height,
+            end: (pen_x, baseline - self.ascent()),
+        }
+    }
+}
+
+impl Drop for GlyphAtlas {
+    fn drop(&mut self) {
+        unsafe { glDeleteTextures(1, &self.texture) };
+    }
+}
+
+struct Layout {
+    // (x, y, u, v, t0) для GL_TRIANGLES
+    vertices: Vec<f32>,
+    width: f32,
+    height: f32,
+    // Верхний левый угол позиции после последнего символа
+    end: (f32, f32),
+}
+
+fn push_quad(vertices: &mut Vec<f32>, rect: [f32; 4], uv: [f32; 4], t: f32) {
+    let [x0, y0, x1, y1] = rect;
+    let [u0, v0, u1, v1] = uv;
+    vertices.extend_from_slice(&[
+        x0, y0, u0, v0, t, x1, y0, u1, v0, t, x0, y1, u0, v1, t, //
+        x1, y0, u1, v0, t, x1, y1, u1, v1, t, x0, y1, u0, v1, t,
+    ]);
+}
+
+// RGBA-картинка для штампов
+pub struct Image {
+    texture: u32,
+    width: f32,
+    height: f32,
+}
+
+pub fn load_image(path: &Path) -> Result<Image, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    buf.truncate(info.buffer_size());
+
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("indexed PNG is not supported".to_string()),
+    };
+
+    let mut texture = 0u32;
+    unsafe {
+        glGenTextures(1, &mut texture);
+        glBindTexture(GL_TEXTURE_2D, texture);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as c_int);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as c_int);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as c_int);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as c_int);
+        glTexImage2D(
+            GL_TEXTURE_2D,
+            0,
+            GL_RGBA as c_int,
+            info.width as c_int,
+            info.height as c_int,
+            0,
+            GL_RGBA,
+            GL_UNSIGNED_BYTE,
+            rgba.as_ptr() as *const c_void,
+        );
+    }
+
+    Ok(Image {
+        texture,
+        width: info.width as f32,
+        height: info.height as f32,
+    })
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe { glDeleteTextures(1, &self.texture) };
+    }
+}
+
+fn load_stamp_images(config: &Config) -> HashMap<String, Image> {
+    let mut images = HashMap::new();
+    for name in config.stamps.iter().filter(|s| s.ends_with(".png")) {
+        let path = Config::find(name);
+        match load_image(&path) {
+            Ok(image) => {
+                images.insert(name.clone(), image);
+            }
+            Err(e) => eprintln!("[stamp] failed to load {}: {}", path.display(), e),
+        }
+    }
+    images
+}
+
+pub struct TextRenderer {
+    pub atlas: GlyphAtlas,
+    images: HashMap<String, Image>,
+    program: u32,
+    vbo: u32,
+    u_now_loc: c_int,
+    u_ttl_loc: c_int,
+    u_fade_start_loc: c_int,
+    u_color_loc: c_int,
+    u_pinned_loc: c_int,
+    u_image_loc: c_int,
+    pos_loc: c_int,
+    uv_loc: c_int,
+    t0_loc: c_int,
+}
+
+impl TextRenderer {
+    pub fn new(config: &Config, proj: &[f32; 16]) -> Result<Self, String> {
+        let program = create_program(
+            include_str!("../shaders/text_vertex.glsl"),
+            include_str!("../shaders/text_fragment.glsl"),
+        )?;
+        unsafe {
+            glUseProgram(program);
+            let proj_loc = glGetUniformLocation(program, c"proj".as_ptr());
+            glUniformMatrix4fv(proj_loc, 1, 0, proj.as_ptr());
+            glUniform1i(glGetUniformLocation(program, c"u_tex".as_ptr()), 0);
+
+            let mut vbo = 0u32;
+            glGenBuffers(1, &mut vbo);
+
+            Ok(Self {
+                atlas: GlyphAtlas::new(&config.font, config.font_size),
+                images: load_stamp_images(config),
+                program,
+                vbo,
+                u_now_loc: glGetUniformLocation(program, c"u_now".as_ptr()),
+                u_ttl_loc: glGetUniformLocation(program, c"u_ttl".as_ptr()),
+                u_fade_start_loc: glGetUniformLocation(program, c"u_fade_start".as_ptr()),
+                u_color_loc: glGetUniformLocation(program, c"u_color".as_ptr()),
+                u_pinned_loc: glGetUniformLocation(program, c"u_pinned".as_ptr()),
+                u_image_loc: glGetUniformLocation(program, c"u_image".as_ptr()),
+                pos_loc: glGetAttribLocation(program, c"pos".as_ptr()),
+                uv_loc: glGetAttribLocation(program, c"uv".as_ptr()),
+                t0_loc: glGetAttribLocation(program, c"t0".as_ptr()),
+            })
+        }
+    }
+
+    // Шрифт и картинки штампов после смены конфига; старые текстуры
+    // освобождаются при замене
+    pub fn reload(&mut self, config: &Config) {
+        self.atlas = GlyphAtlas::new(&config.font, config.font_size);
+        self.images = load_stamp_images(config);
+    }
+
+    // Переключает на программу текста; после отрисовки вызывающий возвращает свою
+    pub fn begin(&self, now: f32, config: &Config) {
+        unsafe {
+            glUseProgram(self.program);
+            glBindTexture(GL_TEXTURE_2D, self.atlas.texture);
+            glUniform1f(self.u_now_loc, now);
+            glUniform1f(self.u_ttl_loc, config.ttl);
+            glUniform1f(self.u_fade_start_loc, config.fade_start());
+        }
+    }
+
+    pub fn draw_label(
+        &mut self,
+        label: &TextLabel,
+        color: [f32; 4],
+        background: Option<[f32; 4]>,
+        pinned: bool,
+    ) -> (f32, f32) {
+        let layout = self.atlas.layout(&label.text, label.x, label.y, label.t);
+
+        if let Some(bg) = background {
+            if layout.width > 0.0 {
+                let mut quad = Vec::new();
+                push_quad(
+                    &mut quad,
+                    [
+                        label.x - BOX_PADDING,
+                        label.y - BOX_PADDING,
+                        label.x + layout.width + BOX_PADDING,
+                        label.y + layout.height + BOX_PADDING,
+                    ],
+                    self.atlas.white_uv(),
+                    label.t,
+                );
+                self.draw(&quad, bg, pinned);
+            }
+        }
+        self.draw(&layout.vertices, color, pinned);
+        (layout.width, layout.height)
+    }
+
+    pub fn draw_centered(&mut self, text: &str, center: (f32, f32), t: f32, color: [f32; 4]) {
+        let size = self.atlas.layout(text, 0.0, 0.0, t);
+        let x = center.0 - size.width / 2.0;
+        let y = center.1 - size.height / 2.0;
+        let layout = self.atlas.layout(text, x, y, t);
+        self.draw(&layout.vertices, color, false);
+    }
+
+    // Ширина и высота строки без отрисовки
+    pub fn measure(&mut self, text: &str) -> (f32, f32) {
+        let layout = self.atlas.layout(text, 0.0, 0.0, 0.0);
+        (layout.width, layout.height)
+    }
+
+    // Картинка вписывается в квадрат size x size с центром в center
+    pub fn draw_image(&self, name: &str, center: (f32, f32), size: f32, t: f32, opacity: f32) {
+        let Some(image) = self.images.get(name) else {
+            return;
+        };
+        let scale = size / image.width.max(image.height);
+        let (hw, hh) = (image.width * scale / 2.0, image.height * scale / 2.0);
+
+        let mut quad = Vec::new();
+        push_quad(
+            &mut quad,
+            [center.0 - hw, center.1 - hh, center.0 + hw, center.1 + hh],
+            [0.0, 0.0, 1.0, 1.0],
+            t,
+        );
+        unsafe {
+            glBindTexture(GL_TEXTURE_2D, image.texture);
+            glUniform1f(self.u_image_loc, 1.0);
+            self.draw(&quad, [1.0, 1.0, 1.0, opacity], false);
+            glUniform1f(self.u_image_loc, 0.0);
+            glBindTexture(GL_TEXTURE_2D, self.atlas.texture);
+        }
+    }
+
+    // Сплошной прямоугольник [x0, y0, x1, y1], не выцветает
+    pub fn draw_rect(&self, rect: [f32; 4], color: [f32; 4]) {
+        let mut quad = Vec::new();
+        push_quad(&mut quad, rect, self.atlas.white_uv(), 0.0);
+        self.draw(&quad, color, true);
+    }
+
+    // Рамка толщиной width снаружи прямоугольника
+    pub fn draw_frame(&self, rect: [f32; 4], width: f32, color: [f32; 4]) {
+        let [x0, y0, x1, y1] = rect;
+        self.draw_rect([x0 - width, y0 - width, x1 + width, y0], color);
+        self.draw_rect([x0 - width, y1, x1 + width, y1 + width], color);
+        self.draw_rect([x0 - width, y0, x0, y1], color);
+        self.draw_rect([x1, y0, x1 + width, y1], color);
+    }
+
+    pub fn draw_caret(&mut self, label: &TextLabel, color: [f32; 4]) {
+        let (x, y) = self
+            .atlas
+            .layout(&label.text, label.x, label.y, label.t)
+            .end;
+        let line_height = self.atlas.line_height();
+
+        let mut quad = Vec::new();
+        push_quad(
+            &mut quad,
+            [x, y, x + 2.0, y + line_height],
+            self.atlas.white_uv(),
+            label.t,
+        );
+        self.draw(&quad, color, true);
+    }
+
+    fn draw(&self, vertices: &[f32], color: [f32; 4], pinned: bool) {
+        if vertices.is_empty() {
+            return;
+        }
+        unsafe {
+            glUniform4f(self.u_color_loc, color[0], color[1], color[2], color[3]);
+            glUniform1f(self.u_pinned_loc, if pinned { 1.0 } else { 0.0 });
+
+            glBindBuffer(GL_ARRAY_BUFFER, self.vbo);
+            glBufferData(
+                GL_ARRAY_BUFFER,
+                std::mem::size_of_val(vertices) as isize,
+                vertices.as_ptr() as *const c_void,
+                GL_STREAM_DRAW,
+            );
+
+            glEnableVertexAttribArray(self.pos_loc as u32);
+            glVertexAttribPointer(self.pos_loc as u32, 2, GL_FLOAT, 0, 20, std::ptr::null());
+            glEnableVertexAttribArray(self.uv_loc as u32);
+            glVertexAttribPointer(self.uv_loc as u32, 2, GL_FLOAT, 0, 20, 8 as *const c_void);
+            glEnableVertexAttribArray(self.t0_loc as u32);
+            glVertexAttribPointer(self.t0_loc as u32, 1, GL_FLOAT, 0, 20, 16 as *const c_void);
+
+            glDrawArrays(GL_TRIANGLES, 0, (vertices.len() / 5) as c_int);
+
+            glDisableVertexAttribArray(self.pos_loc as u32);
+            glDisableVertexAttribArray(self.uv_loc as u32);
+            glDisableVertexAttribArray(self.t0_loc as u32);
+        }
+    }
+}
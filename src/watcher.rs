@@ -0,0 +1,104 @@
+use std::ffi::{CString, OsStr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// Перечитывание конфига при сохранении: inotify на каталоги файлов конфига.
+// Следим за каталогами, а не за файлами - редакторы часто пишут временный
+// файл и переименовывают его поверх старого
+
+pub struct ConfigWatcher {
+    fd: OwnedFd,
+    // Дескриптор inotify и каталог, за которым он следит
+    dirs: Vec<(i32, PathBuf)>,
+    // Файлы действующего конфига: --config или профиль с цепочкой inherits.
+    // Остальные профили в тех же каталогах перезагрузку не вызывают
+    files: Vec<PathBuf>,
+}
+
+impl ConfigWatcher {
+    pub fn new() -> Option<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            eprintln!(
+                "[watcher] inotify unavailable: {}",
+                std::io::Error::last_os_error()
+            );
+            return None;
+        }
+        Some(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            dirs: Vec::new(),
+            files: Vec::new(),
+        })
+    }
+
+    // Новый набор файлов после перезагрузки или смены профиля. Каталоги
+    // добавляются по мере надобности; несуществующие пропускаются
+    pub fn watch(&mut self, files: Vec<PathBuf>) {
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .filter_map(|file| std::path::absolute(file).ok())
+            .collect();
+        for file in &files {
+            let Some(dir) = file.parent() else {
+                continue;
+            };
+            if !dir.is_dir() || self.dirs.iter().any(|(_, watched)| watched == dir) {
+                continue;
+            }
+            let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+            if wd < 0 {
+                eprintln!(
+                    "[watcher] failed to watch {}: {}",
+                    dir.display(),
+                    std::io::Error::last_os_error()
+                );
+            } else {
+                self.dirs.push((wd, dir.to_path_buf()));
+            }
+        }
+        self.files = files;
+    }
+
+    // Вычитывает накопившиеся события; true - изменился файл конфига.
+    // Несколько событий одного сохранения дают одну перезагрузку
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n <= 0 {
+                return changed;
+            }
+            let mut offset = 0;
+            let header = std::mem::size_of::<libc::inotify_event>();
+            while offset + header <= n as usize {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buf.as_ptr().add(offset) as *const _) };
+                let name = &buf[offset + header..offset + header + event.len as usize];
+                // Имя дополнено нулями до выравнивания
+                let name = OsStr::from_bytes(name.split(|b| *b == 0).next().unwrap_or_default());
+                changed |= self.relevant(event.wd, name);
+                offset += header + event.len as usize;
+            }
+        }
+    }
+
+    fn relevant(&self, wd: i32, name: &OsStr) -> bool {
+        self.dirs
+            .iter()
+            .find(|(watched, _)| *watched == wd)
+            .is_some_and(|(_, dir)| self.files.contains(&dir.join(Path::new(name))))
+    }
+}
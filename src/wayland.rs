@@ -1,80 +1,936 @@
+use crate::annotation::{Annotation, Point, Stamp, StampKind, Stroke};
+use crate::bindings::{Action, Bindings, Mods, Scroll, Trigger};
+use crate::color::Color;
+use crate::config::{
+    Config, HotZone, KeyboardMode, SpotlightShape, Tool, ZoneAction, ZoneActivate,
+};
+use crate::cursor::{CursorKind, Cursors};
+use crate::events::OverlayEvent;
+use crate::fsm::{self, Event, Fsm, Guards, Hooks, Mode};
+use crate::geometry::{circle_hits_rect, snap_segment};
+use crate::keyboard::Xkb;
+use crate::menu::ToolMenu;
+use crate::reentry::{self, ReentryStrategy, Signal};
+use crate::text::TextLabel;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use wayland_client::{
+    backend::ObjectId,
+    event_created_child,
     protocol::{
-        wl_callback, wl_compositor, wl_output, wl_pointer, wl_region, wl_registry, wl_seat,
-        wl_surface,
+        wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_output, wl_pointer, wl_region,
+        wl_registry, wl_seat, wl_shm, wl_shm_pool, wl_surface, wl_touch,
     },
     Connection, Dispatch, Proxy, QueueHandle, WEnum,
 };
+use wayland_cursor::CursorTheme;
+use wayland_protocols::wp::cursor_shape::v1::client::{
+    wp_cursor_shape_device_v1, wp_cursor_shape_manager_v1,
+};
+use wayland_protocols::wp::tablet::zv2::client::{
+    zwp_tablet_manager_v2, zwp_tablet_pad_group_v2, zwp_tablet_pad_ring_v2,
+    zwp_tablet_pad_strip_v2, zwp_tablet_pad_v2, zwp_tablet_seat_v2, zwp_tablet_tool_v2,
+    zwp_tablet_v2,
+};
 use wayland_protocols_wlr::layer_shell::v1::client::{
     zwlr_layer_shell_v1::{self, Layer},
     zwlr_layer_surface_v1,
 };
+use xkeysym::Keysym;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Pointer,
+    Tablet(ObjectId),
+    Touch(i32),
+}
+
+#[derive(Clone, Copy)]
+pub struct PenSample {
+    // 0.0-1.0
+    pub pressure: f32,
+    // Отклонение от вертикали, градусы
+    pub tilt: f32,
+}
 
-pub enum InputState {
-    Capturing,   // full_region - захватываем всё
-    Passthrough, // empty_region - пропускаем всё
+// Состояние инструмента планшета, копится до события frame
+#[derive(Default)]
+pub struct TabletTool {
+    pub eraser: bool,
+    pub in_proximity: bool,
+    pub down: bool,
+    pub pos: (f32, f32),
+    pub pressure: f32,
+    pub tilt: (f32, f32),
+    pressed: bool,
+    released: bool,
 }
 
 pub struct WaylandState {
+    pub config: Config,
+    pub qh: Option<QueueHandle<WaylandState>>,
     pub compositor: Option<wl_compositor::WlCompositor>,
     pub layer_shell: Option<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
+    pub layer_shell_version: u32,
     pub seat: Option<wl_seat::WlSeat>,
     pub outputs: Vec<wl_output::WlOutput>,
+    pub output_names: HashMap<ObjectId, String>,
+    // Выход, на котором сейчас поверхность (wl_surface.enter)
+    pub surface_output: Option<ObjectId>,
     pub surface: Option<wl_surface::WlSurface>,
     pub layer_surface: Option<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1>,
     pub pointer: Option<wl_pointer::WlPointer>,
+    pub keyboard: Option<wl_keyboard::WlKeyboard>,
+    pub xkb: Option<Xkb>,
     pub width: i32,
     pub height: i32,
     pub configured: bool,
-    pub input_state: InputState,
-    pub drawing: bool,
-    pub current_stroke: Vec<(f32, f32, f32)>,
-    pub strokes: Vec<Vec<(f32, f32, f32)>>,
+    // Режим ввода; input region в захвате - capture_region (весь экран или
+    // выделенный прямоугольник), в PASSTHROUGH - passthrough_region (горячие зоны)
+    pub fsm: Fsm,
+    pub touch: Option<wl_touch::WlTouch>,
+    // Незавершённые штрихи - у каждой мыши, пера и пальца свой
+    pub active_strokes: HashMap<InputSource, Stroke>,
+    pub erasers: HashSet<InputSource>,
+    pub items: Vec<Annotation>,
     pub start_time: std::time::Instant,
-    pub empty_region: Option<wl_region::WlRegion>,
-    pub full_region: Option<wl_region::WlRegion>,
-    pub last_poll: std::time::Instant,
-    pub last_scroll: Option<std::time::Instant>,
+    pub passthrough_region: Option<wl_region::WlRegion>,
+    pub capture_region: Option<wl_region::WlRegion>,
+    pub reentry: Box<dyn ReentryStrategy>,
+    pub pointer_pos: (f32, f32),
+    pub spotlight: bool,
+    pub spotlight_shape: SpotlightShape,
+    pub spotlight_radius: f32,
+    pub tool: Tool,
+    pub text_draft: Option<TextLabel>,
+    pub menu: Option<ToolMenu>,
+    // Режим выделения области захвата и начало перетаскивания
+    pub selecting: bool,
+    pub selection_start: Option<(f32, f32)>,
+    // Область захвата [x0, y0, x1, y1]; None - весь экран
+    pub capture_rect: Option<[f32; 4]>,
+    pub stamp_index: usize,
+    pub stamp_counter: u32,
+    // Привязка snap_modifier с клавиатуры и зажатые кнопки с действием snap:
+    // фокус клавиатуры есть не во всех режимах
+    pub snapping: bool,
+    snap_buttons: HashSet<Trigger>,
+    pub bindings: Bindings,
+    pub mods: Mods,
+    pub color: Color,
+    pub width_scale: f32,
+    pub running: bool,
+    // Откуда взят конфиг: файл --config или профиль, и счётчик замен
+    // конфига - по нему главный цикл перезагружает шрифт и картинки штампов
+    pub profile: String,
+    pub config_file: Option<PathBuf>,
+    pub config_generation: u64,
+    // Флаги командной строки относятся к конфигу запуска: после смены
+    // профиля через сокет перезагрузка их больше не применяет
+    pub launch_config: bool,
+    // Кнопка, которой сейчас рисуют мышью, - её отпускание завершает штрих
+    pointer_drag: Option<Trigger>,
+    // Очередь событий для подписчиков сокета; пишется, только пока они есть
+    pub events: Vec<OverlayEvent>,
+    pub record_events: bool,
+    next_stroke_id: u64,
+    pub cursor_shape_manager: Option<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+    pub cursors: Cursors,
+    pub tablet_manager: Option<zwp_tablet_manager_v2::ZwpTabletManagerV2>,
+    pub tablet_seat: Option<zwp_tablet_seat_v2::ZwpTabletSeatV2>,
+    pub tablet_tools: HashMap<ObjectId, TabletTool>,
+    // Где перо было в последний раз - там стратегия stylus и пробует
+    stylus_seen: Option<(f32, f32)>,
+    scroll_accum: f64,
 }
 
 impl WaylandState {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         Self {
+            qh: None,
             compositor: None,
             layer_shell: None,
+            layer_shell_version: 1,
             seat: None,
             outputs: Vec::new(),
+            output_names: HashMap::new(),
+            surface_output: None,
             surface: None,
             layer_surface: None,
             pointer: None,
+            keyboard: None,
+            xkb: None,
             width: 0,
             height: 0,
             configured: false,
-            input_state: InputState::Capturing,
-            drawing: false,
-            current_stroke: Vec::new(),
-            strokes: Vec::new(),
+            fsm: Fsm::new(),
+            touch: None,
+            active_strokes: HashMap::new(),
+            erasers: HashSet::new(),
+            items: Vec::new(),
             start_time: std::time::Instant::now(),
-            empty_region: None,
-            full_region: None,
-            last_poll: std::time::Instant::now(),
-            last_scroll: None,
+            passthrough_region: None,
+            capture_region: None,
+            reentry: reentry::from_config(&config),
+            pointer_pos: (0.0, 0.0),
+            spotlight: false,
+            spotlight_shape: config.spotlight_shape,
+            spotlight_radius: config.spotlight_radius,
+            tool: config.tool,
+            text_draft: None,
+            menu: None,
+            selecting: false,
+            selection_start: None,
+            capture_rect: None,
+            stamp_index: 0,
+            stamp_counter: 1,
+            snapping: false,
+            snap_buttons: HashSet::new(),
+            bindings: Bindings::new(&config.bindings, &config.shortcuts),
+            mods: Mods::default(),
+            color: config.pen.color,
+            width_scale: 1.0,
+            running: true,
+            profile: "default".to_string(),
+            config_file: None,
+            config_generation: 0,
+            launch_config: true,
+            pointer_drag: None,
+            events: Vec::new(),
+            record_events: false,
+            next_stroke_id: 1,
+            cursor_shape_manager: None,
+            cursors: Cursors::default(),
+            tablet_manager: None,
+            tablet_seat: None,
+            tablet_tools: HashMap::new(),
+            stylus_seen: None,
+            scroll_accum: 0.0,
+            config,
+        }
+    }
+
+    // Input region по текущему режиму автомата
+    fn apply_input_region(&mut self) {
+        let (Some(surface), Some(compositor), Some(qh)) =
+            (&self.surface, &self.compositor, &self.qh)
+        else {
+            return;
+        };
+        match self.fsm.mode() {
+            // Проба стратегии stylus: ловим приближение пера рядом с местом,
+            // где его видели, остальной экран остаётся окнам под оверлеем
+            Mode::Passthrough if self.reentry.probing() => {
+                let region = compositor.create_region(qh, ());
+                if let Some((x, y)) = self.stylus_seen {
+                    let r = reentry::PROBE_RADIUS;
+                    region.add(
+                        (x - r) as i32,
+                        (y - r) as i32,
+                        (2.0 * r) as i32,
+                        (2.0 * r) as i32,
+                    );
+                }
+                surface.set_input_region(Some(&region));
+                region.destroy();
+            }
+            Mode::Passthrough => surface.set_input_region(self.passthrough_region.as_ref()),
+            Mode::Hidden => {
+                let empty = compositor.create_region(qh, ());
+                surface.set_input_region(Some(&empty));
+                empty.destroy();
+            }
+            _ => surface.set_input_region(self.capture_region.as_ref()),
         }
+        surface.commit();
+        self.refresh_keyboard_interactivity();
     }
 
-    pub fn set_input_passthrough(&mut self, passthrough: bool) {
-        if let Some(surface) = &self.surface {
-            let region = if passthrough {
-                self.empty_region.as_ref()
+    pub fn set_capturing(&mut self, capturing: bool) -> bool {
+        fsm::fire(
+            self,
+            if capturing {
+                Event::Capture
             } else {
-                self.full_region.as_ref()
+                Event::Release
+            },
+        )
+    }
+
+    fn capturing(&self) -> bool {
+        self.fsm.mode().captures_input()
+    }
+
+    // Раз в кадр: сигналы и решение стратегии возврата
+    pub fn tick_reentry(&mut self) {
+        if reentry::take_toggle_request() {
+            self.run_action(Action::TogglePassthrough);
+        }
+        if reentry::take_visibility_request() {
+            self.run_action(Action::ToggleVisibility);
+        }
+        let probing = self.reentry.probing();
+        let decision = self.reentry.tick(self.capturing());
+        self.follow_reentry(probing, decision);
+    }
+
+    pub fn reentry_signal(&mut self, signal: Signal) {
+        let probing = self.reentry.probing();
+        let decision = self.reentry.signal(signal);
+        self.follow_reentry(probing, decision);
+    }
+
+    // Проба началась или кончилась без смены режима - input region
+    // обновляется сам, без событий режима и смены курсора
+    fn follow_reentry(&mut self, was_probing: bool, decision: Option<bool>) {
+        if let Some(capture) = decision {
+            self.set_capturing(capture);
+        }
+        if self.reentry.probing() != was_probing && self.fsm.mode() == Mode::Passthrough {
+            self.apply_input_region();
+        }
+    }
+
+    // Input region для захвата: весь экран или выделенный прямоугольник
+    fn update_capture_region(&mut self) {
+        let (Some(compositor), Some(qh)) = (&self.compositor, &self.qh) else {
+            return;
+        };
+        let region = compositor.create_region(qh, ());
+        match self.capture_rect {
+            Some([x0, y0, x1, y1]) => {
+                region.add(x0 as i32, y0 as i32, (x1 - x0) as i32, (y1 - y0) as i32)
+            }
+            None => region.add(0, 0, i32::MAX, i32::MAX),
+        }
+        if let Some(old) = self.capture_region.replace(region) {
+            old.destroy();
+        }
+        if self.capturing() {
+            self.apply_input_region();
+        }
+    }
+
+    // Следующее перетаскивание задаёт область захвата; на время выбора
+    // захватывается весь экран
+    pub fn begin_region_select(&mut self) {
+        self.selecting = true;
+        self.selection_start = None;
+        self.capture_rect = None;
+        self.update_capture_region();
+        self.set_capturing(true);
+    }
+
+    fn finish_region_select(&mut self) {
+        self.selecting = false;
+        let Some((sx, sy)) = self.selection_start.take() else {
+            return;
+        };
+        let (x, y) = self.pointer_pos;
+        let rect = [sx.min(x), sy.min(y), sx.max(x), sy.max(y)];
+        // Случайный клик вместо перетаскивания - остаёмся на весь экран
+        self.capture_rect =
+            (rect[2] - rect[0] >= 16.0 && rect[3] - rect[1] >= 16.0).then_some(rect);
+        self.update_capture_region();
+    }
+
+    pub fn clear_region(&mut self) {
+        self.selecting = false;
+        self.selection_start = None;
+        self.capture_rect = None;
+        self.update_capture_region();
+    }
+
+    // Горячие зоны для выхода, на котором стоит поверхность
+    fn hot_zones(&self) -> impl Iterator<Item = &HotZone> {
+        let output = self
+            .surface_output
+            .as_ref()
+            .and_then(|id| self.output_names.get(id));
+        self.config
+            .hot_zones
+            .iter()
+            .filter(move |zone| zone.output.is_none() || zone.output.as_ref() == output)
+    }
+
+    // Input region для PASSTHROUGH: только горячие зоны
+    fn update_passthrough_region(&mut self) {
+        let (Some(compositor), Some(qh)) = (&self.compositor, &self.qh) else {
+            return;
+        };
+        let region = compositor.create_region(qh, ());
+        for zone in self.hot_zones() {
+            let (x, y, w, h) = zone.rect(self.width, self.height);
+            region.add(x, y, w, h);
+        }
+        if let Some(old) = self.passthrough_region.replace(region) {
+            old.destroy();
+        }
+        if self.fsm.mode() == Mode::Passthrough {
+            self.apply_input_region();
+        }
+    }
+
+    // Пока набирается текст - забираем клавиатуру целиком; в режиме захвата
+    // фокус по клику (нужен для модификаторов и сочетаний клавиш),
+    // в PASSTHROUGH - отдаём
+    pub fn refresh_keyboard_interactivity(&mut self) {
+        let mode = self.keyboard_interactivity();
+        if let (Some(surface), Some(layer_surface)) = (&self.surface, &self.layer_surface) {
+            layer_surface.set_keyboard_interactivity(mode);
+            surface.commit();
+        }
+    }
+
+    fn keyboard_interactivity(&self) -> zwlr_layer_surface_v1::KeyboardInteractivity {
+        use zwlr_layer_surface_v1::KeyboardInteractivity;
+
+        if self.text_draft.is_some() {
+            KeyboardInteractivity::Exclusive
+        } else if self.capturing()
+            && self.config.keyboard_interactivity == KeyboardMode::OnDemand
+            && self.layer_shell_version >= 4
+        {
+            KeyboardInteractivity::OnDemand
+        } else {
+            KeyboardInteractivity::None
+        }
+    }
+
+    pub fn begin_text(&mut self, x: f32, y: f32) {
+        self.commit_text(false);
+        let t = self.start_time.elapsed().as_secs_f32();
+        self.text_draft = Some(TextLabel::new(x, y, self.color, t));
+        self.refresh_keyboard_interactivity();
+    }
+
+    pub fn commit_text(&mut self, pinned: bool) {
+        if let Some(mut label) = self.text_draft.take() {
+            if !label.text.trim().is_empty() {
+                label.t = self.start_time.elapsed().as_secs_f32();
+                label.pinned = pinned;
+                self.items.push(Annotation::Text(label));
+            }
+            self.refresh_keyboard_interactivity();
+        }
+    }
+
+    pub fn cancel_text(&mut self) {
+        if self.text_draft.take().is_some() {
+            self.refresh_keyboard_interactivity();
+        }
+    }
+
+    pub fn place_stamp(&mut self, x: f32, y: f32) {
+        let Some(entry) = self.config.stamps.get(self.stamp_index) else {
+            return;
+        };
+        let kind = StampKind::from_entry(entry, self.stamp_counter);
+        if let StampKind::Number(_) = kind {
+            self.stamp_counter += 1;
+        }
+        let t = self.start_time.elapsed().as_secs_f32();
+        self.items.push(Annotation::Stamp(Stamp {
+            x,
+            y,
+            kind,
+            color: self.color,
+            t,
+        }));
+    }
+
+    pub fn undo(&mut self) {
+        if self.text_draft.is_some() {
+            self.cancel_text();
+            return;
+        }
+        match self.items.pop() {
+            // Убрали последний номер - следующий штамп получит его снова
+            Some(Annotation::Stamp(Stamp {
+                kind: StampKind::Number(n),
+                ..
+            })) if n + 1 == self.stamp_counter => self.stamp_counter = n,
+            Some(Annotation::Stroke(stroke)) => {
+                self.emit(|| OverlayEvent::StrokeRemoved { id: stroke.id })
+            }
+            _ => {}
+        }
+    }
+
+    pub fn emit(&mut self, event: impl FnOnce() -> OverlayEvent) {
+        if self.record_events {
+            self.events.push(event());
+        }
+    }
+
+    // Убирает аннотации с истёкшим ttl
+    pub fn expire_items(&mut self, now: f32) {
+        let ttl = self.config.ttl;
+        let mut expired = Vec::new();
+        self.items.retain(|item| {
+            let keep = !item.is_expired(now, ttl);
+            if let (false, Annotation::Stroke(stroke)) = (keep, item) {
+                expired.push(stroke.id);
+            }
+            keep
+        });
+        for id in expired {
+            self.emit(|| OverlayEvent::StrokeExpired { id });
+        }
+    }
+
+    // Нажатие касанием или пером: вне захвата оно доходит до нас только через
+    // горячую зону, при открытом меню выбирает пункт. true - нажатие для инструмента
+    fn press_at(&mut self, x: f32, y: f32) -> bool {
+        if !self.capturing() {
+            self.hot_zone_event(ZoneActivate::Click, (x, y));
+            false
+        } else if self.fsm.mode() == Mode::MenuOpen {
+            self.menu_click((x, y));
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn tool_down(&mut self, source: InputSource, x: f32, y: f32, pen: Option<PenSample>) {
+        match self.tool {
+            Tool::Pen => self.begin_stroke(source, x, y, pen),
+            Tool::Text => self.begin_text(x, y),
+            Tool::Stamp => self.place_stamp(x, y),
+            Tool::Eraser => self.begin_erase(source, x, y),
+        }
+    }
+
+    pub fn tool_motion(&mut self, source: &InputSource, x: f32, y: f32, pen: Option<PenSample>) {
+        if self.active_strokes.contains_key(source) {
+            self.extend_stroke(source, x, y, pen);
+        } else if self.erasers.contains(source) {
+            self.erase_at(x, y);
+        }
+    }
+
+    pub fn tool_up(&mut self, source: &InputSource) {
+        self.finish_input(source);
+        fsm::fire(self, Event::StrokeEnd);
+    }
+
+    // Завершает штрих или стирание источника, не трогая автомат
+    fn finish_input(&mut self, source: &InputSource) {
+        self.erasers.remove(source);
+        self.end_stroke(source);
+    }
+
+    pub fn begin_erase(&mut self, source: InputSource, x: f32, y: f32) {
+        self.erasers.insert(source);
+        self.erase_at(x, y);
+        fsm::fire(self, Event::StrokeStart);
+    }
+
+    pub fn begin_stroke(&mut self, source: InputSource, x: f32, y: f32, pen: Option<PenSample>) {
+        let id = self.new_stroke_id();
+        let stroke = Stroke {
+            id,
+            points: Vec::new(),
+            color: self.color,
+        };
+        self.active_strokes.insert(source.clone(), stroke);
+        let color = self.color;
+        self.emit(|| OverlayEvent::StrokeStarted { id, color, x, y });
+        self.extend_stroke(&source, x, y, pen);
+        fsm::fire(self, Event::StrokeStart);
+    }
+
+    pub fn extend_stroke(&mut self, source: &InputSource, x: f32, y: f32, pen: Option<PenSample>) {
+        let t = self.start_time.elapsed().as_secs_f32();
+        let snapping = self.snapping || !self.snap_buttons.is_empty();
+        let (angle, grid) = (self.config.snap_angle, self.config.snap_grid);
+        let Some(stroke) = self.active_strokes.get(source) else {
+            return;
+        };
+        let w = self.point_width(&stroke.points, x, y, t, pen);
+        let stroke = &mut self.active_strokes.get_mut(source).unwrap().points;
+        match stroke.first().copied() {
+            // С зажатым модификатором штрих - прямой отрезок от точки нажатия
+            Some(first) if snapping => {
+                let (start, end) = snap_segment((first.x, first.y), (x, y), angle, grid);
+                *stroke = vec![
+                    Point {
+                        x: start.0,
+                        y: start.1,
+                        ..first
+                    },
+                    Point {
+                        x: end.0,
+                        y: end.1,
+                        t,
+                        w,
+                    },
+                ];
+            }
+            _ => stroke.push(Point { x, y, t, w }),
+        }
+    }
+
+    // Готовый штрих (replay): время точек сдвигается так, будто он только что закончен
+    pub fn add_stroke(&mut self, color: Color, mut points: Vec<Point>) {
+        let Some(last) = points.last() else {
+            return;
+        };
+        let shift = self.start_time.elapsed().as_secs_f32() - last.t;
+        for point in &mut points {
+            point.t += shift;
+        }
+        let id = self.new_stroke_id();
+        self.emit(|| OverlayEvent::StrokeFinished {
+            id,
+            color,
+            points: points.clone(),
+        });
+        self.items
+            .push(Annotation::Stroke(Stroke { id, points, color }));
+    }
+
+    fn new_stroke_id(&mut self) -> u64 {
+        self.next_stroke_id += 1;
+        self.next_stroke_id - 1
+    }
+
+    pub fn end_stroke(&mut self, source: &InputSource) {
+        if let Some(stroke) = self.active_strokes.remove(source) {
+            if !stroke.points.is_empty() {
+                self.emit(|| OverlayEvent::StrokeFinished {
+                    id: stroke.id,
+                    color: stroke.color,
+                    points: stroke.points.clone(),
+                });
+                self.items.push(Annotation::Stroke(stroke));
+                if self.config.pen.auto_advance {
+                    self.next_color();
+                }
+            }
+        }
+    }
+
+    // Ширина по нажиму пера, а без него - по скорости: чем быстрее, тем тоньше
+    fn point_width(&self, stroke: &[Point], x: f32, y: f32, t: f32, pen: Option<PenSample>) -> f32 {
+        self.width_scale * self.base_width(stroke, x, y, t, pen)
+    }
+
+    fn base_width(&self, stroke: &[Point], x: f32, y: f32, t: f32, pen: Option<PenSample>) -> f32 {
+        let c = &self.config;
+        if !c.variable_width {
+            return c.pen.thickness;
+        }
+        if let Some(pen) = pen {
+            let w = c.stroke_width(1.0 - pen.pressure);
+            // Наклонённое перо рисует шире, как карандаш плашмя
+            return w * (1.0 + (pen.tilt / 90.0).clamp(0.0, 1.0));
+        }
+        let Some(last) = stroke.last() else {
+            return c.stroke_width(0.0);
+        };
+        // w уже умножена на width_scale, сглаживаем в базовых единицах
+        let last_w = last.w / self.width_scale;
+        let dist = ((x - last.x).powi(2) + (y - last.y).powi(2)).sqrt();
+        let speed = dist / (t - last.t).max(0.001);
+        let target = c.stroke_width(speed / c.velocity_scale);
+        // Сглаживаем, чтобы ширина не скакала от точки к точке
+        last_w + (target - last_w) * 0.3
+    }
+
+    pub fn erase_at(&mut self, x: f32, y: f32) {
+        let r = self.config.eraser_size / 2.0;
+        let near = |px: f32, py: f32, extra: f32| {
+            (px - x).powi(2) + (py - y).powi(2) <= (r + extra).powi(2)
+        };
+        let stamp_r = self.config.stamp_size / 2.0;
+        let mut removed = Vec::new();
+        self.items.retain(|item| match item {
+            Annotation::Stroke(stroke) => {
+                let keep = !stroke.points.iter().any(|p| near(p.x, p.y, p.w / 2.0));
+                if !keep {
+                    removed.push(stroke.id);
+                }
+                keep
+            }
+            Annotation::Stamp(stamp) => !near(stamp.x, stamp.y, stamp_r),
+            Annotation::Text(label) => {
+                let (w, h) = label.size;
+                !circle_hits_rect((x, y), r, [label.x, label.y, label.x + w, label.y + h])
+            }
+        });
+        for id in removed {
+            self.emit(|| OverlayEvent::StrokeRemoved { id });
+        }
+    }
+
+    // Нажатие или отпускание привязанной кнопки; false - привязки нет
+    pub fn trigger(&mut self, trigger: Trigger, pressed: bool) -> bool {
+        if !pressed {
+            // Модификаторы могли смениться с нажатия, поэтому без поиска привязки
+            if self.snap_buttons.remove(&trigger) {
+                return true;
+            }
+            if self.pointer_drag == Some(trigger) {
+                self.pointer_drag = None;
+                if self.selecting {
+                    self.finish_region_select();
+                } else {
+                    self.tool_up(&InputSource::Pointer);
+                }
+            }
+            return self.bindings.get(self.mods, trigger).is_some();
+        }
+        let Some(action) = self.bindings.get(self.mods, trigger) else {
+            return false;
+        };
+        match (action, trigger) {
+            (Action::Draw | Action::Erase, Trigger::Button(_)) if self.selecting => {
+                self.pointer_drag = Some(trigger);
+                self.selection_start = Some(self.pointer_pos);
+            }
+            // Нажатия мыши вне захвата сюда не доходят, их разбирают горячие зоны
+            (Action::Draw | Action::Erase, Trigger::Button(_)) => {
+                self.pointer_drag = Some(trigger);
+                let (x, y) = self.pointer_pos;
+                if action == Action::Draw {
+                    self.tool_down(InputSource::Pointer, x, y, None);
+                } else {
+                    self.begin_erase(InputSource::Pointer, x, y);
+                }
+            }
+            // Держится до отпускания; у колеса и клавиш отпускания нет
+            (Action::Snap, Trigger::Button(_) | Trigger::Stylus(_) | Trigger::Pad(_)) => {
+                self.snap_buttons.insert(trigger);
+            }
+            // У пера и планшета рисует само касание, кнопка только выбирает инструмент
+            (Action::Draw, _) => self.set_tool(Tool::Pen),
+            (Action::Erase, _) => self.set_tool(Tool::Eraser),
+            _ => self.run_action(action),
+        }
+        true
+    }
+
+    // Следующий цвет палитры; цвет не из палитры сменяется её первым
+    fn next_color(&mut self) {
+        let palette = &self.config.pen.palette;
+        if palette.is_empty() {
+            return;
+        }
+        let i = palette.iter().position(|c| *c == self.color);
+        self.color = palette[i.map_or(0, |i| (i + 1) % palette.len())];
+    }
+
+    fn cycle_stamp(&mut self, forward: bool) {
+        let n = self.config.stamps.len();
+        if self.tool != Tool::Stamp {
+            self.set_tool(Tool::Stamp);
+        } else if n > 0 {
+            self.stamp_index = if forward {
+                (self.stamp_index + 1) % n
+            } else {
+                (self.stamp_index + n - 1) % n
             };
-            if let Some(r) = region {
-                surface.set_input_region(Some(r));
-                surface.commit();
+        }
+    }
+
+    pub fn run_action(&mut self, action: Action) {
+        match action {
+            Action::Draw => self.set_tool(Tool::Pen),
+            Action::Erase => self.set_tool(Tool::Eraser),
+            Action::Undo => self.undo(),
+            Action::Clear => self.clear_all(),
+            Action::CycleTool => self.set_tool(self.tool.next()),
+            Action::CycleColor => self.next_color(),
+            Action::ThicknessUp => self.width_scale = (self.width_scale * 1.25).min(8.0),
+            Action::ThicknessDown => self.width_scale = (self.width_scale / 1.25).max(0.25),
+            Action::NextStamp => self.cycle_stamp(true),
+            Action::PrevStamp => self.cycle_stamp(false),
+            Action::Passthrough => {
+                self.set_capturing(false);
             }
+            Action::TogglePassthrough => {
+                self.set_capturing(!self.capturing());
+            }
+            Action::ToggleVisibility => {
+                let hidden = self.fsm.mode() == Mode::Hidden;
+                fsm::fire(self, if hidden { Event::Show } else { Event::Hide });
+            }
+            Action::ToggleSpotlight => self.spotlight = !self.spotlight,
+            Action::Color(n) => {
+                if let Some(color) = self.config.pen.palette.get(n) {
+                    self.color = *color;
+                }
+            }
+            Action::SelectRegion => self.begin_region_select(),
+            Action::ClearRegion => self.clear_region(),
+            Action::Quit => self.running = false,
+            // Действует, только пока кнопка зажата, см. trigger
+            Action::Snap | Action::None => {}
         }
     }
+
+    // Указатель, касание или перо в PASSTHROUGH навелись или нажали в горячую зону
+    fn hot_zone_event(&mut self, activate: ZoneActivate, (x, y): (f32, f32)) -> bool {
+        let action = self
+            .hot_zones()
+            .find(|zone| zone.activate == activate && zone.contains(self.width, self.height, x, y))
+            .map(|zone| zone.action);
+        match action {
+            Some(ZoneAction::Capture) => self.set_capturing(true),
+            Some(ZoneAction::Menu) => {
+                self.menu = Some(ToolMenu::new(x, y, self.width as f32, self.height as f32));
+                fsm::fire(self, Event::OpenMenu)
+            }
+            None => false,
+        }
+    }
+
+    // Клик при открытом меню: выбор инструмента или закрытие
+    fn menu_click(&mut self, (x, y): (f32, f32)) {
+        if let Some(tool) = self.menu.as_ref().and_then(|menu| menu.hit(x, y)) {
+            self.set_tool(tool);
+        }
+        fsm::fire(self, Event::CloseMenu);
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.commit_text(false);
+        if self.tool != tool {
+            self.emit(|| OverlayEvent::ToolChanged { tool });
+        }
+        self.tool = tool;
+        self.update_cursor();
+    }
+
+    // Новый конфиг без перезапуска: аннотации остаются, а состояние, взятое
+    // из конфига, меняется только вместе со своим полем - выбранные на ходу
+    // цвет и инструмент правка другого ключа не сбрасывает. Выход не меняется
+    pub fn apply_config(&mut self, config: Config) {
+        let old = std::mem::replace(&mut self.config, config);
+        let config = &self.config;
+        if config.output != old.output {
+            eprintln!("[config] output change needs a restart");
+        }
+        if (
+            config.reentry,
+            config.scroll_cooldown,
+            config.polling_interval,
+        ) != (old.reentry, old.scroll_cooldown, old.polling_interval)
+        {
+            self.reentry = reentry::from_config(config);
+            self.reentry
+                .signal(Signal::StylusSeen(self.stylus_seen.is_some()));
+        }
+        if config.spotlight_shape != old.spotlight_shape {
+            self.spotlight_shape = config.spotlight_shape;
+        }
+        if config.spotlight_radius != old.spotlight_radius {
+            self.spotlight_radius = config.spotlight_radius;
+        }
+        if config.pen.color != old.pen.color {
+            self.color = config.pen.color;
+        }
+        if self.stamp_index >= config.stamps.len() {
+            self.stamp_index = 0;
+        }
+        self.bindings = Bindings::new(&config.bindings, &config.shortcuts);
+        self.config_generation += 1;
+        if config.tool != old.tool {
+            self.set_tool(self.config.tool);
+        }
+        self.update_passthrough_region();
+        self.refresh_keyboard_interactivity();
+        self.update_cursor();
+    }
+
+    // Другой профиль: в отличие от правки файла, цвет, инструмент и
+    // множитель толщины берутся из него заново
+    pub fn switch_profile(&mut self, profile: String, config: Config) {
+        self.apply_config(config);
+        self.color = self.config.pen.color;
+        self.width_scale = 1.0;
+        self.set_tool(self.config.tool);
+        self.config_file = None;
+        self.launch_config = false;
+        if self.profile != profile {
+            self.emit(|| OverlayEvent::ProfileChanged {
+                profile: profile.clone(),
+            });
+        }
+        self.profile = profile;
+    }
+
+    pub fn update_cursor(&mut self) {
+        let (Some(pointer), Some(compositor), Some(qh)) =
+            (&self.pointer, &self.compositor, &self.qh)
+        else {
+            return;
+        };
+        let kind = CursorKind::for_state(self.fsm.mode(), self.tool, self.config.eraser_size);
+        self.cursors.apply(kind, pointer, compositor, qh);
+    }
+
+    pub fn clear_all(&mut self) {
+        self.pointer_drag = None;
+        self.cancel_text();
+        let ids: Vec<u64> = self
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Annotation::Stroke(stroke) => Some(stroke.id),
+                _ => None,
+            })
+            .chain(self.active_strokes.values().map(|stroke| stroke.id))
+            .collect();
+        for id in ids {
+            self.emit(|| OverlayEvent::StrokeRemoved { id });
+        }
+        self.items.clear();
+        self.active_strokes.clear();
+        self.erasers.clear();
+        self.stamp_counter = 1;
+        fsm::fire(self, Event::StrokeEnd);
+    }
+}
+
+impl Hooks for WaylandState {
+    fn fsm(&mut self) -> &mut Fsm {
+        &mut self.fsm
+    }
+
+    fn guards(&self) -> Guards {
+        Guards {
+            editing_text: self.text_draft.is_some(),
+            active_strokes: !self.active_strokes.is_empty() || !self.erasers.is_empty(),
+        }
+    }
+
+    fn on_exit(&mut self, from: Mode, to: Mode) {
+        if from == Mode::MenuOpen {
+            self.menu = None;
+        }
+        // Мышь уходит другим окнам - её штрих заканчивается здесь
+        if !to.captures_input() {
+            self.pointer_drag = None;
+            self.finish_input(&InputSource::Pointer);
+        }
+    }
+
+    fn on_enter(&mut self, to: Mode, from: Mode) {
+        self.emit(|| OverlayEvent::ModeChanged { mode: to, from });
+        if to == Mode::Passthrough {
+            self.reentry.signal(Signal::PassthroughEntered);
+        }
+        let region_changed = to.captures_input() != from.captures_input()
+            || to == Mode::Hidden
+            || from == Mode::Hidden;
+        if region_changed {
+            self.apply_input_region();
+        }
+        self.update_cursor();
+    }
 }
 
 impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
@@ -87,7 +943,9 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
         qh: &QueueHandle<WaylandState>,
     ) {
         if let wl_registry::Event::Global {
-            name, interface, ..
+            name,
+            interface,
+            version,
         } = event
         {
             match interface.as_str() {
@@ -96,10 +954,12 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
                         Some(registry.bind::<wl_compositor::WlCompositor, _, _>(name, 4, qh, ()));
                 }
                 "zwlr_layer_shell_v1" => {
+                    // v4 нужна для KeyboardInteractivity::OnDemand
+                    state.layer_shell_version = version.min(4);
                     state.layer_shell = Some(
                         registry.bind::<zwlr_layer_shell_v1::ZwlrLayerShellV1, _, _>(
                             name,
-                            1,
+                            state.layer_shell_version,
                             qh,
                             (),
                         ),
@@ -108,8 +968,33 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
                 "wl_seat" => {
                     state.seat = Some(registry.bind::<wl_seat::WlSeat, _, _>(name, 5, qh, ()));
                 }
+                "wl_shm" => {
+                    state.cursors.shm = Some(registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ()));
+                }
+                "wp_cursor_shape_manager_v1" => {
+                    state.cursor_shape_manager = Some(
+                        registry.bind::<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, _, _>(
+                            name,
+                            1,
+                            qh,
+                            (),
+                        ),
+                    );
+                }
+                "zwp_tablet_manager_v2" => {
+                    state.tablet_manager = Some(
+                        registry.bind::<zwp_tablet_manager_v2::ZwpTabletManagerV2, _, _>(
+                            name,
+                            1,
+                            qh,
+                            (),
+                        ),
+                    );
+                }
                 "wl_output" => {
-                    let output = registry.bind::<wl_output::WlOutput, _, _>(name, 3, qh, ());
+                    // v4 присылает имя выхода (для горячих зон)
+                    let output =
+                        registry.bind::<wl_output::WlOutput, _, _>(name, version.min(4), qh, ());
                     state.outputs.push(output);
                 }
                 _ => {}
@@ -144,13 +1029,18 @@ impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for WaylandState {
 
 impl Dispatch<wl_surface::WlSurface, ()> for WaylandState {
     fn event(
-        _: &mut Self,
+        state: &mut Self,
         _: &wl_surface::WlSurface,
-        _: wl_surface::Event,
+        event: wl_surface::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<WaylandState>,
     ) {
+        // Горячие зоны задаются по выходу - пересобираем их при переезде
+        if let wl_surface::Event::Enter { output } = event {
+            state.surface_output = Some(output.id());
+            state.update_passthrough_region();
+        }
     }
 }
 
@@ -173,6 +1063,7 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for WaylandState {
             state.height = height as i32;
             layer_surface.ack_configure(serial);
             state.configured = true;
+            state.update_passthrough_region();
         }
     }
 }
@@ -193,6 +1084,12 @@ impl Dispatch<wl_seat::WlSeat, ()> for WaylandState {
             if caps.contains(wl_seat::Capability::Pointer) && state.pointer.is_none() {
                 state.pointer = Some(seat.get_pointer(qh, ()));
             }
+            if caps.contains(wl_seat::Capability::Keyboard) && state.keyboard.is_none() {
+                state.keyboard = Some(seat.get_keyboard(qh, ()));
+            }
+            if caps.contains(wl_seat::Capability::Touch) && state.touch.is_none() {
+                state.touch = Some(seat.get_touch(qh, ()));
+            }
         }
     }
 }
@@ -206,43 +1103,234 @@ impl Dispatch<wl_pointer::WlPointer, ()> for WaylandState {
         _: &Connection,
         _: &QueueHandle<WaylandState>,
     ) {
+        // Мышь над оверлеем в PASSTHROUGH - пробы откладываются. Клик во
+        // время пробы предназначался окну под оверлеем
+        if !state.capturing() && !matches!(event, wl_pointer::Event::Leave { .. }) {
+            let probing = state.reentry.probing();
+            state.reentry_signal(Signal::PointerActivity);
+            if probing {
+                return;
+            }
+        }
         match event {
             wl_pointer::Event::Button {
                 button,
                 state: WEnum::Value(btn_state),
                 ..
             } => {
-                if button == 0x110 {
-                    if btn_state == wl_pointer::ButtonState::Pressed {
-                        state.input_state = InputState::Capturing;
-                        state.set_input_passthrough(false);
-                        state.drawing = true;
-                        state.current_stroke.clear();
-                    } else {
-                        state.drawing = false;
-                        if !state.current_stroke.is_empty() {
-                            state.strokes.push(state.current_stroke.clone());
-                            state.current_stroke.clear();
+                let pressed = btn_state == wl_pointer::ButtonState::Pressed;
+                if pressed && !state.capturing() {
+                    state.hot_zone_event(ZoneActivate::Click, state.pointer_pos);
+                } else if pressed && state.fsm.mode() == Mode::MenuOpen {
+                    state.menu_click(state.pointer_pos);
+                } else {
+                    state.trigger(Trigger::Button(button), pressed);
+                }
+            }
+            wl_pointer::Event::Axis { axis, value, .. } => {
+                // Без модификаторов колесо при включённом прожекторе меняет
+                // его размер/форму. Штампы перебираются привязкой (shift+колесо),
+                // простое колесо остаётся выходом в PASSTHROUGH
+                let plain = state.mods == Mods::default();
+                if state.spotlight && plain {
+                    match axis {
+                        // Форма - по направлению, а не переключением: жест
+                        // тачпада шлёт десятки событий
+                        WEnum::Value(wl_pointer::Axis::HorizontalScroll) => {
+                            state.spotlight_shape = if value < 0.0 {
+                                SpotlightShape::Circle
+                            } else {
+                                SpotlightShape::Rect
+                            };
+                        }
+                        _ => {
+                            state.spotlight_radius =
+                                (state.spotlight_radius - value as f32 * 2.0).clamp(20.0, 2000.0);
                         }
                     }
+                    return;
+                }
+                let direction = match axis {
+                    WEnum::Value(wl_pointer::Axis::HorizontalScroll) if value > 0.0 => {
+                        Scroll::Right
+                    }
+                    WEnum::Value(wl_pointer::Axis::HorizontalScroll) => Scroll::Left,
+                    _ if value > 0.0 => Scroll::Down,
+                    _ => Scroll::Up,
+                };
+                let trigger = Trigger::Scroll(direction);
+                if let Some(Action::Passthrough | Action::TogglePassthrough) =
+                    state.bindings.get(state.mods, trigger)
+                {
+                    // Уход в PASSTHROUGH - с первого же события колеса. Один жест
+                    // даёт пачку событий, поэтому переключатель на колесе только
+                    // отпускает ввод и не захватывает его обратно
+                    state.scroll_accum = 0.0;
+                    if state.capturing() {
+                        state.run_action(Action::Passthrough);
+                    }
+                    return;
+                }
+                // Остальные действия - раз на щелчок колеса, а не на каждое событие
+                state.scroll_accum += value;
+                if state.scroll_accum.abs() >= 10.0 {
+                    state.scroll_accum = 0.0;
+                    state.trigger(trigger, true);
                 }
             }
-            wl_pointer::Event::Axis { .. } => {
-                state.input_state = InputState::Passthrough;
-                state.set_input_passthrough(true);
-                state.drawing = false;
-                state.last_scroll = Some(std::time::Instant::now());
+            // В PASSTHROUGH указатель может попасть к нам только через горячую зону
+            wl_pointer::Event::Enter {
+                serial,
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer_pos = (surface_x as f32, surface_y as f32);
+                state.cursors.serial = Some(serial);
+                state.update_cursor();
+                if !state.capturing() {
+                    state.hot_zone_event(ZoneActivate::Hover, state.pointer_pos);
+                }
             }
+            wl_pointer::Event::Leave { .. } => state.cursors.serial = None,
             wl_pointer::Event::Motion {
                 surface_x,
                 surface_y,
                 ..
             } => {
-                if state.drawing {
-                    let t = state.start_time.elapsed().as_secs_f32();
-                    state
-                        .current_stroke
-                        .push((surface_x as f32, surface_y as f32, t));
+                state.pointer_pos = (surface_x as f32, surface_y as f32);
+                state.tool_motion(
+                    &InputSource::Pointer,
+                    surface_x as f32,
+                    surface_y as f32,
+                    None,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_touch::WlTouch, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wl_touch::WlTouch,
+        event: wl_touch::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            // Касание во время пробы предназначалось окну под оверлеем
+            wl_touch::Event::Down { .. } if state.reentry.probing() => {}
+            wl_touch::Event::Down { id, x, y, .. } => {
+                let (x, y) = (x as f32, y as f32);
+                if state.press_at(x, y) {
+                    state.tool_down(InputSource::Touch(id), x, y, None);
+                }
+            }
+            wl_touch::Event::Motion { id, x, y, .. } => {
+                state.tool_motion(&InputSource::Touch(id), x as f32, y as f32, None);
+            }
+            wl_touch::Event::Up { id, .. } => state.tool_up(&InputSource::Touch(id)),
+            // Композитор забрал касания себе (жест) - недорисованное выбрасываем
+            wl_touch::Event::Cancel => {
+                let mut cancelled = Vec::new();
+                state.active_strokes.retain(|source, stroke| {
+                    let touch = matches!(source, InputSource::Touch(_));
+                    if touch {
+                        cancelled.push(stroke.id);
+                    }
+                    !touch
+                });
+                for id in cancelled {
+                    state.emit(|| OverlayEvent::StrokeRemoved { id });
+                }
+                state
+                    .erasers
+                    .retain(|source| !matches!(source, InputSource::Touch(_)));
+                fsm::fire(state, Event::StrokeEnd);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => {
+                state.xkb = Xkb::from_fd(fd, size);
+                if state.xkb.is_none() {
+                    eprintln!("[keyboard] failed to load keymap");
+                }
+            }
+            wl_keyboard::Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
+                if let Some(xkb) = state.xkb.as_mut() {
+                    xkb.update_modifiers(mods_depressed, mods_latched, mods_locked, group);
+                    state.snapping = xkb.mod_active(&state.config.snap_modifier);
+                    state.mods = Mods {
+                        ctrl: xkb.mod_active("Control"),
+                        shift: xkb.mod_active("Shift"),
+                        alt: xkb.mod_active("Mod1"),
+                        logo: xkb.mod_active("Mod4"),
+                    };
+                }
+            }
+            // Без фокуса модификаторы не приходят - считаем их отпущенными
+            wl_keyboard::Event::Leave { .. } => {
+                state.snapping = false;
+                state.mods = Mods::default();
+            }
+            wl_keyboard::Event::Key {
+                key,
+                state: WEnum::Value(wl_keyboard::KeyState::Pressed),
+                ..
+            } => {
+                let Some(xkb) = state.xkb.as_ref() else {
+                    return;
+                };
+                let (sym, text) = xkb.key(key);
+                let ctrl = xkb.mod_active("Control");
+                let shift = xkb.mod_active("Shift");
+                let Some(draft) = state.text_draft.as_mut() else {
+                    // Сочетание ищем и по введённому символу, и по клавише без
+                    // модификаторов: "Z" с Shift тоже найдёт "ctrl+shift+z"
+                    let base = xkb.base_key(key);
+                    if !state.trigger(Trigger::Key(sym.raw()), true) {
+                        state.trigger(Trigger::Key(base.raw()), true);
+                    }
+                    return;
+                };
+                match sym {
+                    Keysym::Return | Keysym::KP_Enter if shift => draft.text.push('\n'),
+                    Keysym::Return | Keysym::KP_Enter => state.commit_text(ctrl),
+                    Keysym::Escape => state.cancel_text(),
+                    Keysym::BackSpace => {
+                        draft.text.pop();
+                    }
+                    _ => {
+                        if let Some(text) = text.filter(|t| !t.chars().any(char::is_control)) {
+                            draft.text.push_str(&text);
+                        }
+                    }
                 }
             }
             _ => {}
@@ -252,13 +1340,16 @@ impl Dispatch<wl_pointer::WlPointer, ()> for WaylandState {
 
 impl Dispatch<wl_output::WlOutput, ()> for WaylandState {
     fn event(
-        _: &mut Self,
-        _: &wl_output::WlOutput,
-        _: wl_output::Event,
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<WaylandState>,
     ) {
+        if let wl_output::Event::Name { name } = event {
+            state.output_names.insert(output.id(), name);
+        }
     }
 }
 
@@ -286,6 +1377,279 @@ impl Dispatch<wl_region::WlRegion, ()> for WaylandState {
     }
 }
 
+impl Dispatch<wl_shm::WlShm, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &wl_shm::WlShm,
+        _: wl_shm::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &wl_shm_pool::WlShmPool,
+        _: wl_shm_pool::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+// Буфер курсора-ластика не меняется, release можно не ждать
+impl Dispatch<wl_buffer::WlBuffer, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &wl_buffer::WlBuffer,
+        _: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+        _: wp_cursor_shape_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<wp_cursor_shape_device_v1::WpCursorShapeDeviceV1, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &wp_cursor_shape_device_v1::WpCursorShapeDeviceV1,
+        _: wp_cursor_shape_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<zwp_tablet_manager_v2::ZwpTabletManagerV2, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_manager_v2::ZwpTabletManagerV2,
+        _: zwp_tablet_manager_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<zwp_tablet_seat_v2::ZwpTabletSeatV2, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        event: zwp_tablet_seat_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        if let zwp_tablet_seat_v2::Event::ToolAdded { id } = event {
+            state.tablet_tools.insert(id.id(), TabletTool::default());
+        }
+    }
+
+    event_created_child!(WaylandState, zwp_tablet_seat_v2::ZwpTabletSeatV2, [
+        zwp_tablet_seat_v2::EVT_TABLET_ADDED_OPCODE => (zwp_tablet_v2::ZwpTabletV2, ()),
+        zwp_tablet_seat_v2::EVT_TOOL_ADDED_OPCODE => (zwp_tablet_tool_v2::ZwpTabletToolV2, ()),
+        zwp_tablet_seat_v2::EVT_PAD_ADDED_OPCODE => (zwp_tablet_pad_v2::ZwpTabletPadV2, ()),
+    ]);
+}
+
+impl Dispatch<zwp_tablet_v2::ZwpTabletV2, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_v2::ZwpTabletV2,
+        _: zwp_tablet_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<zwp_tablet_tool_v2::ZwpTabletToolV2, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        tool: &zwp_tablet_tool_v2::ZwpTabletToolV2,
+        event: zwp_tablet_tool_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let id = tool.id();
+        if let zwp_tablet_tool_v2::Event::Removed = event {
+            // Перо могли убрать, не отпустив: штрих завершается, как при отрыве
+            state.tool_up(&InputSource::Tablet(id.clone()));
+            state.tablet_tools.remove(&id);
+            if state.tablet_tools.is_empty() {
+                state.stylus_seen = None;
+                state.reentry_signal(Signal::StylusSeen(false));
+            }
+            tool.destroy();
+            return;
+        }
+        let proximity = match event {
+            zwp_tablet_tool_v2::Event::ProximityIn { .. } => Some(true),
+            zwp_tablet_tool_v2::Event::ProximityOut => Some(false),
+            _ => None,
+        };
+        let Some(t) = state.tablet_tools.get_mut(&id) else {
+            return;
+        };
+        match event {
+            zwp_tablet_tool_v2::Event::Type { tool_type } => {
+                t.eraser = tool_type == WEnum::Value(zwp_tablet_tool_v2::Type::Eraser);
+            }
+            zwp_tablet_tool_v2::Event::ProximityIn { .. } => t.in_proximity = true,
+            zwp_tablet_tool_v2::Event::ProximityOut => {
+                t.in_proximity = false;
+                if t.down {
+                    t.down = false;
+                    t.released = true;
+                }
+            }
+            zwp_tablet_tool_v2::Event::Down { .. } => {
+                t.down = true;
+                t.pressed = true;
+            }
+            zwp_tablet_tool_v2::Event::Up => {
+                t.down = false;
+                t.released = true;
+            }
+            zwp_tablet_tool_v2::Event::Motion { x, y } => t.pos = (x as f32, y as f32),
+            zwp_tablet_tool_v2::Event::Pressure { pressure } => {
+                t.pressure = pressure as f32 / 65535.0;
+            }
+            zwp_tablet_tool_v2::Event::Tilt { tilt_x, tilt_y } => {
+                t.tilt = (tilt_x as f32, tilt_y as f32);
+            }
+            zwp_tablet_tool_v2::Event::Button {
+                button,
+                state: WEnum::Value(btn_state),
+                ..
+            } => {
+                let pressed = btn_state == zwp_tablet_tool_v2::ButtonState::Pressed;
+                state.trigger(Trigger::Stylus(button), pressed);
+            }
+            zwp_tablet_tool_v2::Event::Frame { .. } => {
+                let (x, y) = t.pos;
+                let eraser = t.eraser;
+                let (pressed, released, down) = (t.pressed, t.released, t.down);
+                let pen = PenSample {
+                    pressure: t.pressure,
+                    tilt: t.tilt.0.hypot(t.tilt.1),
+                };
+                t.pressed = false;
+                t.released = false;
+                if state.stylus_seen.replace((x, y)).is_none() {
+                    state.reentry_signal(Signal::StylusSeen(true));
+                }
+
+                let source = InputSource::Tablet(id);
+                state.pointer_pos = (x, y);
+                if pressed && state.press_at(x, y) {
+                    if eraser {
+                        state.begin_erase(source.clone(), x, y);
+                    } else {
+                        state.tool_down(source.clone(), x, y, Some(pen));
+                    }
+                } else if down {
+                    state.tool_motion(&source, x, y, Some(pen));
+                }
+                if released {
+                    state.tool_up(&source);
+                }
+            }
+            _ => {}
+        }
+        if let Some(near) = proximity {
+            state.reentry_signal(Signal::StylusProximity(near));
+        }
+    }
+}
+
+impl Dispatch<zwp_tablet_pad_v2::ZwpTabletPadV2, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &zwp_tablet_pad_v2::ZwpTabletPadV2,
+        event: zwp_tablet_pad_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        if let zwp_tablet_pad_v2::Event::Button {
+            button,
+            state: WEnum::Value(btn_state),
+            ..
+        } = event
+        {
+            let pressed = btn_state == zwp_tablet_pad_v2::ButtonState::Pressed;
+            state.trigger(Trigger::Pad(button), pressed);
+        }
+    }
+
+    event_created_child!(WaylandState, zwp_tablet_pad_v2::ZwpTabletPadV2, [
+        zwp_tablet_pad_v2::EVT_GROUP_OPCODE => (zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2, ()),
+    ]);
+}
+
+impl Dispatch<zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2,
+        _: zwp_tablet_pad_group_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+
+    event_created_child!(WaylandState, zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2, [
+        zwp_tablet_pad_group_v2::EVT_RING_OPCODE => (zwp_tablet_pad_ring_v2::ZwpTabletPadRingV2, ()),
+        zwp_tablet_pad_group_v2::EVT_STRIP_OPCODE => (zwp_tablet_pad_strip_v2::ZwpTabletPadStripV2, ()),
+    ]);
+}
+
+impl Dispatch<zwp_tablet_pad_ring_v2::ZwpTabletPadRingV2, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_pad_ring_v2::ZwpTabletPadRingV2,
+        _: zwp_tablet_pad_ring_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<zwp_tablet_pad_strip_v2::ZwpTabletPadStripV2, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_pad_strip_v2::ZwpTabletPadStripV2,
+        _: zwp_tablet_pad_strip_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
 pub fn setup_wayland(
     state: &mut WaylandState,
     conn: &Connection,
@@ -302,8 +1666,34 @@ pub fn setup_wayland(
     let compositor = state.compositor.as_ref().unwrap();
     let layer_shell = state.layer_shell.as_ref().unwrap();
 
+    if let (Some(manager), Some(seat)) = (&state.tablet_manager, &state.seat) {
+        state.tablet_seat = Some(manager.get_tablet_seat(seat, &qh, ()));
+    }
+
+    // Без wp_cursor_shape_v1 курсоры берутся из темы (XCURSOR_THEME, XCURSOR_SIZE)
+    if let (Some(manager), Some(pointer)) = (&state.cursor_shape_manager, &state.pointer) {
+        state.cursors.shape_device = Some(manager.get_pointer(pointer, &qh, ()));
+    } else if let Some(shm) = &state.cursors.shm {
+        match CursorTheme::load(conn, shm.clone(), 24) {
+            Ok(theme) => state.cursors.theme = Some(theme),
+            Err(e) => eprintln!("[cursor] failed to load cursor theme: {}", e),
+        }
+    }
+
     let surface = compositor.create_surface(&qh, ());
-    let output = state.outputs.first().cloned();
+    let output = match &state.config.output {
+        Some(name) => {
+            let found = state
+                .outputs
+                .iter()
+                .find(|output| state.output_names.get(&output.id()) == Some(name));
+            if found.is_none() {
+                eprintln!("[wayland] output {} not found, using the default", name);
+            }
+            found.or(state.outputs.first()).cloned()
+        }
+        None => state.outputs.first().cloned(),
+    };
 
     let layer_surface = layer_shell.get_layer_surface(
         &surface,
@@ -316,19 +1706,12 @@ pub fn setup_wayland(
 
     layer_surface.set_anchor(zwlr_layer_surface_v1::Anchor::all());
     layer_surface.set_exclusive_zone(-1);
-    layer_surface.set_keyboard_interactivity(zwlr_layer_surface_v1::KeyboardInteractivity::None);
-
-    let empty_region = compositor.create_region(&qh, ());
-    let full_region = compositor.create_region(&qh, ());
-    full_region.add(0, 0, i32::MAX, i32::MAX);
-
-    surface.set_input_region(Some(&full_region));
-    surface.commit();
+    layer_surface.set_keyboard_interactivity(state.keyboard_interactivity());
 
+    state.qh = Some(qh.clone());
     state.surface = Some(surface.clone());
     state.layer_surface = Some(layer_surface);
-    state.empty_region = Some(empty_region);
-    state.full_region = Some(full_region);
+    state.update_capture_region();
 
     event_queue.roundtrip(state).unwrap();
 
@@ -0,0 +1,131 @@
+use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+
+// Каталоги по XDG Base Directory. Относительные пути в переменных по
+// спецификации игнорируются. Разбор значений - в функциях *_from, которые
+// получают переменные параметрами: их можно проверять, не трогая окружение
+
+fn var(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name).map(PathBuf::from)
+}
+
+fn absolute(path: Option<&Path>) -> Option<&Path> {
+    path.filter(|path| path.is_absolute())
+}
+
+// $HOME, а без него - домашний каталог из passwd
+pub fn home() -> PathBuf {
+    home_from(var("HOME").as_deref())
+}
+
+fn home_from(home: Option<&Path>) -> PathBuf {
+    if let Some(home) = absolute(home) {
+        return home.to_path_buf();
+    }
+    unsafe {
+        let entry = libc::getpwuid(libc::getuid());
+        if !entry.is_null() && !(*entry).pw_dir.is_null() {
+            let dir = CStr::from_ptr((*entry).pw_dir).to_string_lossy();
+            return PathBuf::from(dir.into_owned());
+        }
+    }
+    PathBuf::from("/")
+}
+
+pub fn config_home() -> PathBuf {
+    base_from(var("XDG_CONFIG_HOME").as_deref(), &home(), ".config")
+}
+
+// Состояние, которое cherta пишет сама: снимки экспорта
+pub fn state_home() -> PathBuf {
+    base_from(var("XDG_STATE_HOME").as_deref(), &home(), ".local/state")
+}
+
+// $XDG_*_HOME или его значение по умолчанию внутри домашнего каталога
+fn base_from(value: Option<&Path>, home: &Path, default: &str) -> PathBuf {
+    absolute(value).map_or_else(|| home.join(default), Path::to_path_buf)
+}
+
+// Системные каталоги конфига по убыванию приоритета
+pub fn config_dirs() -> Vec<PathBuf> {
+    config_dirs_from(std::env::var("XDG_CONFIG_DIRS").ok().as_deref())
+}
+
+fn config_dirs_from(dirs: Option<&str>) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = dirs
+        .unwrap_or_default()
+        .split(':')
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .collect();
+    if dirs.is_empty() {
+        vec![PathBuf::from("/etc/xdg")]
+    } else {
+        dirs
+    }
+}
+
+// Без $XDG_RUNTIME_DIR - свой каталог во временном, а не общий /tmp
+pub fn runtime_dir() -> PathBuf {
+    runtime_dir_from(var("XDG_RUNTIME_DIR").as_deref(), unsafe { libc::getuid() })
+}
+
+fn runtime_dir_from(value: Option<&Path>, uid: u32) -> PathBuf {
+    absolute(value).map_or_else(
+        || std::env::temp_dir().join(format!("runtime-{}", uid)),
+        Path::to_path_buf,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_dirs_fall_back_to_home() {
+        let home = Path::new("/home/test");
+        assert_eq!(
+            base_from(None, home, ".config"),
+            PathBuf::from("/home/test/.config")
+        );
+        assert_eq!(
+            base_from(Some(Path::new("relative")), home, ".local/state"),
+            PathBuf::from("/home/test/.local/state")
+        );
+        assert_eq!(
+            base_from(Some(Path::new("/cfg")), home, ".config"),
+            PathBuf::from("/cfg")
+        );
+    }
+
+    #[test]
+    fn home_without_home_var() {
+        assert!(home_from(None).is_absolute());
+        assert!(home_from(Some(Path::new("relative"))).is_absolute());
+        assert_eq!(
+            home_from(Some(Path::new("/home/test"))),
+            PathBuf::from("/home/test")
+        );
+    }
+
+    #[test]
+    fn config_dirs_default_to_etc_xdg() {
+        assert_eq!(config_dirs_from(None), [PathBuf::from("/etc/xdg")]);
+        assert_eq!(config_dirs_from(Some("")), [PathBuf::from("/etc/xdg")]);
+        assert_eq!(
+            config_dirs_from(Some("/a:rel::/b")),
+            [PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+    }
+
+    #[test]
+    fn runtime_dir_without_xdg() {
+        let dir = runtime_dir_from(None, 1000);
+        assert!(dir.starts_with(std::env::temp_dir()));
+        assert!(dir.ends_with("runtime-1000"));
+        assert_eq!(
+            runtime_dir_from(Some(Path::new("/run/user/1")), 1000),
+            PathBuf::from("/run/user/1")
+        );
+    }
+}